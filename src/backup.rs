@@ -0,0 +1,138 @@
+use crate::device::bytes_to_hex;
+use crate::image::{ConfigImage, Packet};
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Which unit a backup was taken from.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Identity {
+    pub vid: u16,
+    pub pid: u16,
+    pub serial: Option<String>,
+    pub path: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub model: Option<String>,
+}
+
+/// Where the image in a backup came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    /// Read back from the device with GET_REPORT.
+    Device,
+    /// Encoded from the saved state of the last successful write.
+    Cache,
+}
+
+/// The complete 48-packet image, byte for byte, with where and when it was
+/// taken.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Backup {
+    pub device: Identity,
+    /// Seconds since the Unix epoch.
+    pub created_at: u64,
+    pub source: Source,
+    /// One hex string per packet.
+    pub packets: Vec<String>,
+}
+
+impl Backup {
+    pub fn new(device: Identity, source: Source, image: &ConfigImage) -> Self {
+        Self {
+            device,
+            created_at: crate::state::now(),
+            source,
+            packets: image.packets().iter().map(|p| bytes_to_hex(p)).collect(),
+        }
+    }
+
+    pub fn image(&self) -> Result<ConfigImage> {
+        let packets = self
+            .packets
+            .iter()
+            .enumerate()
+            .map(|(i, text)| {
+                let bytes = hex::decode(text.trim()).map_err(|e| anyhow!("packet {i}: {e}"))?;
+                Packet::try_from(bytes.as_slice())
+                    .map_err(|_| anyhow!("packet {i}: expected 8 bytes, found {}", bytes.len()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        ConfigImage::from_packets(&packets)
+    }
+}
+
+/// `$XDG_STATE_HOME/redgear-a15/backups`, where backups go by default.
+pub fn backup_dir() -> Result<PathBuf> {
+    Ok(crate::state::state_dir()?.join("backups"))
+}
+
+pub fn save(path: &Path, backup: &Backup) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    }
+    fs::write(path, serde_json::to_string_pretty(backup)?)
+        .with_context(|| format!("writing {}", path.display()))
+}
+
+/// Reads a backup and checks that its packets form a valid image.
+pub fn load(path: &Path) -> Result<(Backup, ConfigImage)> {
+    let text = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    let backup: Backup =
+        serde_json::from_str(&text).with_context(|| format!("parsing {}", path.display()))?;
+    let image = backup
+        .image()
+        .with_context(|| format!("invalid image in {}", path.display()))?;
+    Ok((backup, image))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LedMode, MouseConfig};
+
+    #[test]
+    fn backup_round_trips_byte_for_byte() {
+        let cfg = MouseConfig {
+            led_mode: LedMode::Waltz,
+            ..MouseConfig::default()
+        };
+        let image = ConfigImage::encode(&cfg);
+        let backup = Backup::new(
+            Identity {
+                vid: 0x1bcf,
+                pid: 0x08a0,
+                serial: Some("A15-1".into()),
+                ..Identity::default()
+            },
+            Source::Device,
+            &image,
+        );
+        let path =
+            std::env::temp_dir().join(format!("redgear-a15-backup-{}.json", std::process::id()));
+        save(&path, &backup).unwrap();
+        let (loaded, loaded_image) = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, backup);
+        assert_eq!(loaded_image, image);
+    }
+
+    #[test]
+    fn corrupt_packets_are_rejected() {
+        let mut backup = Backup::new(
+            Identity::default(),
+            Source::Cache,
+            &ConfigImage::encode(&MouseConfig::default()),
+        );
+        backup.packets[4] = "0407zz".into();
+        assert!(backup.image().is_err());
+        backup.packets[4] = "040700".into();
+        assert!(backup.image().is_err());
+        backup.packets.pop();
+        assert!(backup.image().is_err());
+    }
+}
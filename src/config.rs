@@ -0,0 +1,75 @@
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// `$XDG_CONFIG_HOME/redgear-a15`, falling back to `~/.config/redgear-a15`.
+pub fn config_dir() -> Result<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME").filter(|v| !v.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(".config"))
+            .ok_or_else(|| anyhow!("neither XDG_CONFIG_HOME nor HOME is set"))?,
+    };
+    Ok(base.join("redgear-a15"))
+}
+
+/// Settings read from `config.toml`. Command-line flags and environment
+/// variables take precedence over anything set here.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileConfig {
+    /// `VID:PID` in hex, e.g. `1bcf:08a0`.
+    pub device: Option<String>,
+    pub path: Option<String>,
+    pub serial: Option<String>,
+    /// USB interface number carrying the config reports.
+    pub interface: Option<i32>,
+}
+
+/// Loads `file`, or `config.toml` in `config_dir()` when none is given. A
+/// missing default file is not an error, a missing explicit one is.
+pub fn load(file: Option<&Path>) -> Result<FileConfig> {
+    let (path, explicit) = match file {
+        Some(file) => (file.to_path_buf(), true),
+        None => match config_dir() {
+            Ok(dir) => (dir.join("config.toml"), false),
+            Err(_) => return Ok(FileConfig::default()),
+        },
+    };
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if !explicit && e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(FileConfig::default());
+        }
+        Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+    };
+    toml::from_str(&text).with_context(|| format!("parsing {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_device_section() {
+        let cfg: FileConfig =
+            toml::from_str("device = \"1bcf:08a1\"\nserial = \"A15-02\"\n").unwrap();
+        assert_eq!(cfg.device.as_deref(), Some("1bcf:08a1"));
+        assert_eq!(cfg.serial.as_deref(), Some("A15-02"));
+        assert_eq!(cfg.path, None);
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(toml::from_str::<FileConfig>("vid = 1\n").is_err());
+    }
+
+    #[test]
+    fn missing_explicit_file_is_an_error() {
+        let path = env::temp_dir().join("redgear-a15-no-such-config.toml");
+        assert!(load(Some(&path)).is_err());
+    }
+}
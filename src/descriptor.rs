@@ -0,0 +1,84 @@
+//! Just enough of a HID report descriptor parser to tell which report IDs an
+//! interface declares Feature items for.
+
+const TYPE_MAIN: u8 = 0;
+const TYPE_GLOBAL: u8 = 1;
+const MAIN_FEATURE: u8 = 0xb;
+const GLOBAL_REPORT_ID: u8 = 0x8;
+const GLOBAL_PUSH: u8 = 0xa;
+const GLOBAL_POP: u8 = 0xb;
+const LONG_ITEM: u8 = 0xfe;
+
+/// Report IDs with at least one Feature item, in ascending order. Items
+/// declared before any Report ID belong to report 0.
+pub fn feature_report_ids(desc: &[u8]) -> Vec<u8> {
+    let mut ids = Vec::new();
+    let mut report_id = 0u8;
+    let mut stack = Vec::new();
+    let mut i = 0;
+    while i < desc.len() {
+        let prefix = desc[i];
+        if prefix == LONG_ITEM {
+            let size = desc.get(i + 1).copied().unwrap_or(0) as usize;
+            i += 3 + size;
+            continue;
+        }
+        let size = match prefix & 0x03 {
+            3 => 4,
+            n => n as usize,
+        };
+        let data = desc.get(i + 1..i + 1 + size).unwrap_or(&[]);
+        match ((prefix >> 2) & 0x03, prefix >> 4) {
+            (TYPE_GLOBAL, GLOBAL_REPORT_ID) => report_id = data.first().copied().unwrap_or(0),
+            (TYPE_GLOBAL, GLOBAL_PUSH) => stack.push(report_id),
+            (TYPE_GLOBAL, GLOBAL_POP) => report_id = stack.pop().unwrap_or(report_id),
+            (TYPE_MAIN, MAIN_FEATURE) => ids.push(report_id),
+            _ => {}
+        }
+        i += 1 + size;
+    }
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Boot mouse: buttons and X/Y/wheel input, no features.
+    const MOUSE: &[u8] = &[
+        0x05, 0x01, 0x09, 0x02, 0xa1, 0x01, 0x09, 0x01, 0xa1, 0x00, 0x05, 0x09, 0x19, 0x01, 0x29,
+        0x03, 0x15, 0x00, 0x25, 0x01, 0x95, 0x03, 0x75, 0x01, 0x81, 0x02, 0x95, 0x01, 0x75, 0x05,
+        0x81, 0x03, 0x05, 0x01, 0x09, 0x30, 0x09, 0x31, 0x09, 0x38, 0x15, 0x81, 0x25, 0x7f, 0x75,
+        0x08, 0x95, 0x03, 0x81, 0x06, 0xc0, 0xc0,
+    ];
+
+    /// Vendor page 0xff00 with an input report 1 and a 7-byte feature report 4.
+    const VENDOR: &[u8] = &[
+        0x06, 0x00, 0xff, 0x09, 0x01, 0xa1, 0x01, 0x85, 0x01, 0x09, 0x01, 0x15, 0x00, 0x26, 0xff,
+        0x00, 0x75, 0x08, 0x95, 0x07, 0x81, 0x02, 0x85, 0x04, 0x09, 0x02, 0xb1, 0x02, 0xc0,
+    ];
+
+    #[test]
+    fn boot_mouse_has_no_feature_reports() {
+        assert!(feature_report_ids(MOUSE).is_empty());
+    }
+
+    #[test]
+    fn vendor_interface_declares_report_4() {
+        assert_eq!(feature_report_ids(VENDOR), [4]);
+    }
+
+    #[test]
+    fn push_and_pop_restore_the_report_id() {
+        let desc = [0x85, 0x02, 0xa4, 0x85, 0x04, 0xb1, 0x02, 0xb4, 0xb1, 0x02];
+        assert_eq!(feature_report_ids(&desc), [2, 4]);
+    }
+
+    #[test]
+    fn truncated_descriptor_does_not_panic() {
+        assert!(feature_report_ids(&[0x85]).is_empty());
+        assert!(feature_report_ids(&[0xfe, 0x10]).is_empty());
+    }
+}
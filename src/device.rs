@@ -0,0 +1,685 @@
+use crate::error::Error;
+use crate::image::{self, ConfigImage, Packet};
+use crate::output::{self, detail, say};
+use crate::pacing::Pacing;
+use crate::session::{self, Session, SessionError, Step};
+use crate::transport::Transport;
+use crate::{RESET, YELLOW};
+use anyhow::{Result, bail};
+use serde::Serialize;
+
+/// How often a write that failed or read back wrong is repeated by default.
+pub const WRITE_RETRIES: usize = 2;
+
+/// How a write is paced and how hard it's retried.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WriteOptions {
+    pub pacing: Pacing,
+    /// Extra attempts at the whole write after the first one fails.
+    pub retries: usize,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            pacing: Pacing::default(),
+            retries: WRITE_RETRIES,
+        }
+    }
+}
+
+pub fn bytes_to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// Outcome of one packet: its SET_REPORT(s) and the GET_REPORTs polled
+/// after them.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PacketResult {
+    /// Which write this packet belongs to, counting from 1.
+    pub attempt: usize,
+    pub index: usize,
+    pub sent: String,
+    /// Whether the device acknowledged the packet.
+    pub ok: bool,
+    /// Times the packet was sent.
+    pub tries: usize,
+    /// Last GET_REPORT response.
+    pub readback: Option<String>,
+    /// From the SET_REPORT that got acknowledged to the acknowledgement.
+    pub latency_us: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Sends `packets` as one write session, appending one entry per attempted
+/// packet to `results` so callers still have them when this returns an
+/// error. Nothing is sent unless the packets form a complete session; see
+/// `session::Session` for how each one is acknowledged.
+pub fn send_report_to_mouse(
+    packets: Vec<Vec<u8>>,
+    dev: &impl Transport,
+    pacing: &Pacing,
+    results: &mut Vec<PacketResult>,
+) -> Result<()> {
+    session::check(&packets)?;
+    detail!("> Sending feature reports...");
+    let mut session = Session::new(dev, pacing, results);
+    for packet in &packets {
+        session.send(packet)?;
+    }
+    session.finish()?;
+    drain_input_reports(dev);
+    Ok(())
+}
+
+/// Logs any interrupt-in reports the device queued while it was being
+/// written, so they show up next to the packets that caused them.
+fn drain_input_reports(dev: &impl Transport) {
+    let mut buf = [0u8; 64];
+    while let Ok(len @ 1..) = dev.read(&mut buf, 0) {
+        output::packet(format_args!("< INPUT {}", bytes_to_hex(&buf[..len])), None);
+    }
+}
+
+/// What reading the configuration back after a write showed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum Verification {
+    /// Every data packet read back as written.
+    Match,
+    /// These packets, by index in the full image, came back different.
+    Mismatch { packets: Vec<usize> },
+    /// The configuration couldn't be read back, so nothing was checked.
+    Unavailable { reason: String },
+}
+
+/// Reads the configuration back with `read_config_from_mouse` after a
+/// write of `image` and compares it with the data packets of `image`. The
+/// A-15 answers GET_REPORT with the last packet it was sent, which after a
+/// write is the commit, so there it comes out `Unavailable`.
+pub fn verify(dev: &impl Transport, image: &ConfigImage) -> Verification {
+    let packets = match read_config_from_mouse(dev) {
+        Ok(packets) => packets,
+        Err(e) => {
+            return Verification::Unavailable {
+                reason: format!("readback failed: {e:#}"),
+            };
+        }
+    };
+    if packets.len() != image::DATA_COUNT {
+        return Verification::Unavailable {
+            reason: format!(
+                "the device handed back {} of {} data packets",
+                packets.len(),
+                image::DATA_COUNT
+            ),
+        };
+    }
+    if packets.windows(2).all(|w| w[0] == w[1]) {
+        return Verification::Unavailable {
+            reason: "the device hands back copies of one packet".into(),
+        };
+    }
+    let differing: Vec<usize> = (image::DATA_START..)
+        .zip(&packets)
+        .filter(|&(i, got)| *got != image.packets()[i])
+        .map(|(i, _)| i)
+        .collect();
+    if differing.is_empty() {
+        Verification::Match
+    } else {
+        Verification::Mismatch { packets: differing }
+    }
+}
+
+/// Packets of `image` that the device acknowledged with something else in
+/// `results` (one attempt at writing it), by index in the full image.
+fn handed_back_differently(image: &ConfigImage, results: &[PacketResult]) -> Vec<usize> {
+    results
+        .iter()
+        .filter(|r| !r.ok)
+        .filter(|r| {
+            r.readback
+                .as_ref()
+                .is_some_and(|got| *got != bytes_to_hex(&image.packets()[r.index]))
+        })
+        .map(|r| r.index)
+        .collect()
+}
+
+/// How `write_verified` ended.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct WriteOutcome {
+    pub attempts: usize,
+    /// Readback after the last attempt, or the packet it stopped at when
+    /// that was handed back different; `None` if it failed otherwise.
+    pub verification: Option<Verification>,
+    /// Session step the last failed attempt stopped at.
+    pub failed_step: Option<Step>,
+    /// `None` when no rollback was needed or there was nothing to roll back to.
+    pub rolled_back: Option<bool>,
+    pub error: Option<String>,
+}
+
+impl WriteOutcome {
+    /// The failure, if any: a readback mismatch when a packet of the last
+    /// attempt came back different, a send failure otherwise.
+    pub fn failure(&self) -> Option<Error> {
+        let msg = self.error.clone()?;
+        Some(match self.verification {
+            Some(Verification::Mismatch { .. }) => Error::ReadbackMismatch(msg),
+            _ => Error::SendFailed(msg),
+        })
+    }
+}
+
+/// Writes `image`, checking each packet's acknowledgement, then reads the
+/// configuration back with `verify`. Starts over up to `opts.retries` times
+/// when a send fails or a packet comes back different. If every attempt
+/// fails, `known_good` is written back so the device isn't left
+/// half-configured. When the configuration can't be read back the write
+/// counts as done, with `Verification::Unavailable` saying nothing was
+/// checked, unless the packets weren't acknowledged either: then nothing at
+/// all confirms it and it fails, without retrying or rolling back, which
+/// would be just as unconfirmed.
+pub fn write_verified(
+    dev: &impl Transport,
+    image: &ConfigImage,
+    known_good: Option<&ConfigImage>,
+    opts: &WriteOptions,
+    results: &mut Vec<PacketResult>,
+) -> WriteOutcome {
+    let mut outcome = WriteOutcome {
+        attempts: 0,
+        verification: None,
+        failed_step: None,
+        rolled_back: None,
+        error: None,
+    };
+    let mut error = String::new();
+    for attempt in 1..=opts.retries + 1 {
+        outcome.attempts = attempt;
+        if attempt > 1 {
+            say!(
+                "{YELLOW}WARN:{RESET} {error}, retrying ({attempt} of {})",
+                opts.retries + 1
+            );
+        }
+        let start = results.len();
+        let sent = send_report_to_mouse(image.to_reports(), dev, &opts.pacing, results);
+        for result in &mut results[start..] {
+            result.attempt = attempt;
+        }
+        outcome.failed_step = None;
+        if let Err(e) = sent {
+            outcome.failed_step = e.downcast_ref::<SessionError>().and_then(|e| e.step());
+            // Only a packet that came back different says anything about
+            // the readback; the rest simply weren't sent.
+            let differing = handed_back_differently(image, &results[start..]);
+            outcome.verification =
+                (!differing.is_empty()).then_some(Verification::Mismatch { packets: differing });
+            error = format!("{e:#}");
+            continue;
+        }
+        let verification = verify(dev, image);
+        outcome.verification = Some(verification.clone());
+        let unacknowledged = results[start..].iter().filter(|r| !r.ok).count();
+        match verification {
+            Verification::Match => return outcome,
+            Verification::Unavailable { .. } if unacknowledged == 0 => return outcome,
+            Verification::Unavailable { reason } => {
+                outcome.error = Some(format!(
+                    "{unacknowledged} of {} packets weren't acknowledged and the configuration \
+                     couldn't be read back ({reason}); nothing confirms the mouse took the write",
+                    image::PACKET_COUNT
+                ));
+                return outcome;
+            }
+            Verification::Mismatch { packets } => {
+                error = format!("packets {packets:?} read back differently");
+            }
+        }
+    }
+
+    let attempts = outcome.attempts;
+    let mut message = format!(
+        "write failed after {attempts} attempt{}: {error}",
+        if attempts == 1 { "" } else { "s" }
+    );
+    match known_good {
+        Some(good) => {
+            say!("{YELLOW}WARN:{RESET} Restoring the last known-good config");
+            let mut rollback = Vec::new();
+            let restored =
+                match send_report_to_mouse(good.to_reports(), dev, &opts.pacing, &mut rollback) {
+                    Err(e) => Err(format!("{e:#}")),
+                    Ok(()) => match verify(dev, good) {
+                        Verification::Mismatch { packets } => {
+                            Err(format!("packets {packets:?} read back differently"))
+                        }
+                        _ => Ok(()),
+                    },
+                };
+            outcome.rolled_back = Some(restored.is_ok());
+            match restored {
+                Ok(()) => message.push_str("; restored the last known-good config"),
+                Err(e) => message.push_str(&format!(
+                    "; restoring the last known-good config failed too: {e}"
+                )),
+            }
+        }
+        None => message.push_str("; no known-good config to restore"),
+    }
+    outcome.error = Some(message);
+    outcome
+}
+
+/// The A-15 has no documented read command, so this only issues GET_REPORTs
+/// (nothing is written) and keeps the `0x04 0x07` data packets the device
+/// hands back. Whether they form a valid image is left to `image::decode`.
+pub fn read_config_from_mouse(dev: &impl Transport) -> Result<Vec<Packet>> {
+    let mut packets = Vec::with_capacity(image::DATA_COUNT);
+    for _ in 0..image::DATA_COUNT {
+        let mut buf = [0u8; image::PACKET_LEN];
+        buf[0] = image::REPORT_ID;
+        let len = dev.get_feature_report(&mut buf)?;
+        output::packet(
+            format_args!("< GET_REPORT {}", bytes_to_hex(&buf[..len])),
+            None,
+        );
+        if len == image::PACKET_LEN && buf[1] == image::OP_DATA {
+            packets.push(buf);
+        }
+    }
+    Ok(packets)
+}
+
+/// `read_config_from_mouse`, trusted only if it decodes with nothing unknown
+/// or mismatched. A device that echoes the last SET_REPORT hands back copies
+/// of one packet, which mustn't be mistaken for its configuration.
+pub fn read_image(dev: &impl Transport) -> Result<ConfigImage> {
+    let packets = read_config_from_mouse(dev)?;
+    let decoded = image::decode(&packets)?;
+    if !decoded.is_clean() {
+        bail!(
+            "readback didn't decode cleanly ({} unknown bytes, {} mismatched pairs)",
+            decoded.unknown.len(),
+            decoded.mismatched_pairs.len()
+        );
+    }
+    ConfigImage::from_packets(&packets)
+}
+
+/// Whether the interface answers a GET_REPORT for feature report 0x04, the
+/// one every config packet goes through. Nothing is written.
+pub fn accepts_config_reports(dev: &impl Transport) -> bool {
+    let mut buf = [0u8; image::PACKET_LEN];
+    buf[0] = image::REPORT_ID;
+    matches!(dev.get_feature_report(&mut buf), Ok(1..))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::ConfigImage;
+    use crate::transport::mock::{Event, MockTransport, Response};
+    use crate::{DpiVal, MouseConfig};
+
+    fn image(cfg: &MouseConfig) -> ConfigImage {
+        ConfigImage::encode(cfg)
+    }
+
+    fn pacing() -> Pacing {
+        Pacing {
+            min_delay_ms: 0,
+            timeout_ms: 50,
+            retries: 2,
+        }
+    }
+
+    #[test]
+    fn apply_sends_every_packet_then_reads_it_back() {
+        let dev = MockTransport::new();
+        let img = image(&MouseConfig::default());
+        send_report_to_mouse(img.to_reports(), &dev, &pacing(), &mut Vec::new()).unwrap();
+
+        assert_eq!(dev.sent(), img.to_reports());
+        let events = dev.events();
+        assert_eq!(events.len(), 2 * image::PACKET_COUNT);
+        for (pair, pkt) in events.chunks(2).zip(img.to_reports()) {
+            assert_eq!(pair, [Event::Set(pkt.clone()), Event::Get(pkt)]);
+        }
+    }
+
+    #[test]
+    fn apply_stops_at_the_first_failed_send() {
+        let dev = MockTransport::new();
+        dev.fail_send(5);
+        let reports = image(&MouseConfig::default()).to_reports();
+        let mut results = Vec::new();
+        assert!(send_report_to_mouse(reports.clone(), &dev, &pacing(), &mut results).is_err());
+        assert_eq!(dev.sent(), reports[..5]);
+        assert_eq!(results.len(), 6);
+        assert!(results[..5].iter().all(|r| r.ok));
+        assert!(!results[5].ok);
+    }
+
+    #[test]
+    fn apply_drains_queued_input_reports() {
+        let dev = MockTransport::new();
+        dev.queue_read(vec![0x01, 0x00])
+            .queue_read(vec![0x01, 0x01]);
+        let reports = image(&MouseConfig::default()).to_reports();
+        send_report_to_mouse(reports, &dev, &pacing(), &mut Vec::new()).unwrap();
+        let mut buf = [0u8; 8];
+        assert_eq!(dev.read(&mut buf, 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn unacknowledged_polls_keep_going_until_the_echo() {
+        let dev = MockTransport::new();
+        dev.respond(Response::Error("stall"))
+            .respond(Response::Report(vec![0x04, 0x00, 0, 0, 0, 0, 0, 0]));
+        let reports = image(&MouseConfig::default()).to_reports();
+        let mut results = Vec::new();
+        send_report_to_mouse(reports.clone(), &dev, &pacing(), &mut results).unwrap();
+        assert_eq!(dev.sent(), reports);
+        // The stall isn't recorded as an event, the stray report is.
+        assert_eq!(dev.events().len(), 2 * image::PACKET_COUNT + 1);
+        assert!(
+            results
+                .iter()
+                .all(|r| r.ok && r.tries == 1 && r.error.is_none() && r.latency_us.is_some())
+        );
+    }
+
+    #[test]
+    fn unacknowledged_packet_is_resent_then_fails() {
+        let dev = MockTransport::new();
+        let other = vec![0x04, 0x00, 0, 0, 0, 0, 0, 0];
+        dev.respond(Response::Echo)
+            .respond_with([other.clone(), other.clone(), other]);
+        let pacing = Pacing {
+            timeout_ms: 0,
+            ..pacing()
+        };
+        let reports = image(&MouseConfig::default()).to_reports();
+        let mut results = Vec::new();
+        assert!(send_report_to_mouse(reports.clone(), &dev, &pacing, &mut results).is_err());
+        assert_eq!(dev.sent()[1..], vec![reports[1].clone(); 3]);
+        assert_eq!(results.len(), 2);
+        assert!(!results[1].ok);
+        assert_eq!(results[1].tries, 3);
+    }
+
+    /// Scripts a device that echoes the first `at` packets, then answers
+    /// `answer` to every try at the next one.
+    fn script_wrong_answer(dev: &MockTransport, at: usize, answer: &[u8], tries: usize) {
+        for _ in 0..at {
+            dev.respond(Response::Echo);
+        }
+        dev.respond_with(vec![answer.to_vec(); tries]);
+    }
+
+    /// Scripts a device that acknowledges the next `acks` packets, then
+    /// hands back the data packets of `img` when read.
+    fn script_readback(dev: &MockTransport, acks: usize, img: &ConfigImage) {
+        for _ in 0..acks {
+            dev.respond(Response::Echo);
+        }
+        dev.respond_with(
+            img.to_reports()[image::DATA_START..image::DATA_START + image::DATA_COUNT].to_vec(),
+        );
+    }
+
+    fn opts(retries: usize) -> WriteOptions {
+        WriteOptions {
+            pacing: Pacing {
+                timeout_ms: 0,
+                ..pacing()
+            },
+            retries,
+        }
+    }
+
+    #[test]
+    fn write_is_verified_by_reading_it_back() {
+        let img = image(&MouseConfig::default());
+        let dev = MockTransport::new();
+        script_readback(&dev, image::PACKET_COUNT, &img);
+        let outcome = write_verified(&dev, &img, None, &opts(2), &mut Vec::new());
+        assert_eq!(outcome.error, None);
+        assert_eq!(outcome.attempts, 1);
+        assert_eq!(outcome.verification, Some(Verification::Match));
+        assert_eq!(dev.sent(), img.to_reports());
+        // One acknowledgement per packet, then one GET_REPORT per data page.
+        assert_eq!(
+            dev.events().len(),
+            2 * image::PACKET_COUNT + image::DATA_COUNT
+        );
+    }
+
+    #[test]
+    fn echoed_commit_is_not_a_verification() {
+        let img = image(&MouseConfig::default());
+        let dev = MockTransport::new();
+        let outcome = write_verified(&dev, &img, None, &opts(2), &mut Vec::new());
+        assert_eq!(outcome.error, None);
+        assert!(matches!(
+            outcome.verification,
+            Some(Verification::Unavailable { .. })
+        ));
+    }
+
+    #[test]
+    fn readback_that_differs_fails_the_write() {
+        let img = image(&MouseConfig::default());
+        let other = image(&MouseConfig {
+            dpi: DpiVal::DPI8,
+            ..MouseConfig::default()
+        });
+        let dev = MockTransport::new();
+        script_readback(&dev, image::PACKET_COUNT, &other);
+        let outcome = write_verified(&dev, &img, None, &opts(0), &mut Vec::new());
+        assert_eq!(
+            outcome.verification,
+            Some(Verification::Mismatch {
+                packets: vec![image::DPI.packet]
+            })
+        );
+        assert_eq!(outcome.failure().unwrap().exit_code(), 7);
+    }
+
+    #[test]
+    fn device_that_never_answers_is_not_reported_written() {
+        let img = image(&MouseConfig::default());
+        let good = image(&MouseConfig {
+            dpi: DpiVal::DPI7,
+            ..MouseConfig::default()
+        });
+        let dev = MockTransport::new();
+        for _ in 0..3 {
+            dev.respond(Response::Error("broken pipe"));
+        }
+        let mut results = Vec::new();
+        let outcome = write_verified(&dev, &img, Some(&good), &opts(2), &mut results);
+        assert_eq!(outcome.attempts, 1);
+        assert_eq!(outcome.rolled_back, None);
+        assert!(matches!(
+            outcome.verification,
+            Some(Verification::Unavailable { .. })
+        ));
+        assert_eq!(outcome.failure().unwrap().exit_code(), 6);
+        assert!(results.iter().all(|r| !r.ok));
+        // Sent once, with no retry or rollback after it.
+        assert_eq!(dev.sent()[2..], img.to_reports()[..]);
+    }
+
+    #[test]
+    fn verification_needs_every_data_packet_handed_back() {
+        let img = image(&MouseConfig::default());
+        let dev = MockTransport::new();
+        let pages = &img.to_reports()[image::DATA_START..image::DATA_START + image::DATA_COUNT];
+        dev.respond_with(pages[..10].to_vec())
+            .respond(Response::Error("stall"));
+        assert!(matches!(
+            verify(&dev, &img),
+            Verification::Unavailable { .. }
+        ));
+
+        let dev = MockTransport::new();
+        dev.respond_with(pages.to_vec());
+        assert_eq!(verify(&dev, &img), Verification::Match);
+    }
+
+    #[test]
+    fn mismatched_readback_is_retried() {
+        let img = image(&MouseConfig::default());
+        let other = image(&MouseConfig {
+            dpi: DpiVal::DPI8,
+            ..MouseConfig::default()
+        });
+        let dev = MockTransport::new();
+        script_wrong_answer(
+            &dev,
+            image::DPI.packet,
+            &other.to_reports()[image::DPI.packet],
+            1,
+        );
+        script_readback(&dev, image::PACKET_COUNT, &img);
+        let mut results = Vec::new();
+        let outcome = write_verified(&dev, &img, None, &opts(2), &mut results);
+        assert_eq!(outcome.error, None);
+        assert_eq!(outcome.attempts, 2);
+        assert_eq!(outcome.verification, Some(Verification::Match));
+        assert_eq!(results.len(), image::DPI.packet + 1 + image::PACKET_COUNT);
+        assert_eq!(results.last().unwrap().attempt, 2);
+    }
+
+    #[test]
+    fn late_data_page_restarts_the_write_from_begin() {
+        let img = image(&MouseConfig::default());
+        let reports = img.to_reports();
+        let page = image::DATA_START + 5;
+        let dev = MockTransport::new();
+        script_wrong_answer(&dev, page, &reports[page - 1], 1);
+        script_readback(&dev, image::PACKET_COUNT, &img);
+        let outcome = write_verified(&dev, &img, None, &opts(2), &mut Vec::new());
+        assert_eq!(outcome.error, None);
+        assert_eq!(outcome.attempts, 2);
+        assert_eq!(outcome.verification, Some(Verification::Match));
+        // The page went out once, then the whole session again.
+        let sent = dev.sent();
+        assert_eq!(sent[..=page], reports[..=page]);
+        assert_eq!(sent[page + 1..], reports[..]);
+    }
+
+    #[test]
+    fn failed_write_rolls_back_to_the_known_good_image() {
+        let img = image(&MouseConfig::default());
+        let good = image(&MouseConfig {
+            dpi: DpiVal::DPI7,
+            ..MouseConfig::default()
+        });
+        let dev = MockTransport::new();
+        let stale = &good.to_reports()[image::DPI.packet];
+        for _ in 0..2 {
+            script_wrong_answer(&dev, image::DPI.packet, stale, 1);
+        }
+        let outcome = write_verified(&dev, &img, Some(&good), &opts(1), &mut Vec::new());
+        assert!(outcome.error.is_some());
+        assert_eq!(outcome.attempts, 2);
+        assert_eq!(outcome.rolled_back, Some(true));
+        assert_eq!(outcome.failure().unwrap().exit_code(), 7);
+        assert_eq!(
+            outcome.verification,
+            Some(Verification::Mismatch {
+                packets: vec![image::DPI.packet]
+            })
+        );
+        let sent = dev.sent();
+        assert_eq!(sent[sent.len() - image::PACKET_COUNT..], good.to_reports());
+    }
+
+    #[test]
+    fn send_failure_is_retried_from_the_start() {
+        let img = image(&MouseConfig::default());
+        let dev = MockTransport::new();
+        dev.fail_send(5);
+        script_readback(&dev, 5 + image::PACKET_COUNT, &img);
+        let outcome = write_verified(&dev, &img, None, &opts(1), &mut Vec::new());
+        assert_eq!(outcome.error, None);
+        assert_eq!(outcome.attempts, 2);
+        assert_eq!(outcome.verification, Some(Verification::Match));
+        assert_eq!(dev.sent()[5..], img.to_reports()[..]);
+    }
+
+    #[test]
+    fn failure_without_a_known_good_image_is_reported() {
+        let img = image(&MouseConfig::default());
+        let dev = MockTransport::new();
+        dev.fail_send(5);
+        let outcome = write_verified(&dev, &img, None, &opts(0), &mut Vec::new());
+        assert_eq!(outcome.rolled_back, None);
+        assert_eq!(outcome.failed_step, Some(Step::Data));
+        assert!(matches!(outcome.failure(), Some(Error::SendFailed(_))));
+        let error = outcome.error.unwrap();
+        assert!(error.contains("no known-good config"), "{error}");
+    }
+
+    #[test]
+    fn readback_decodes_scripted_image() {
+        let cfg = MouseConfig {
+            dpi: DpiVal::DPI5,
+            repeat: 11,
+            ..MouseConfig::default()
+        };
+        let dev = MockTransport::new();
+        dev.respond_with(
+            image(&cfg)
+                .to_reports()
+                .into_iter()
+                .filter(|p| p[1] == 0x07),
+        );
+        let decoded = image::decode(&read_config_from_mouse(&dev).unwrap()).unwrap();
+        assert!(decoded.is_clean());
+        assert_eq!(decoded.config, cfg);
+        assert!(dev.sent().is_empty());
+    }
+
+    #[test]
+    fn readback_of_an_echoing_device_does_not_decode() {
+        let dev = MockTransport::new();
+        dev.respond_with([vec![0x04, 0x02, 0, 0, 0, 0, 0, 0]]);
+        let packets = read_config_from_mouse(&dev).unwrap();
+        assert!(image::decode(&packets).is_err());
+    }
+
+    #[test]
+    fn echoed_pages_are_not_taken_for_an_image() {
+        let img = image(&MouseConfig::default());
+        let dev = MockTransport::new();
+        let page = img.to_reports()[20].clone();
+        dev.respond_with(std::iter::repeat_n(page, image::DATA_COUNT));
+        assert!(read_image(&dev).is_err());
+
+        let dev = MockTransport::new();
+        dev.respond_with(
+            img.to_reports()[image::DATA_START..image::DATA_START + image::DATA_COUNT].to_vec(),
+        );
+        assert_eq!(read_image(&dev).unwrap(), img);
+    }
+
+    #[test]
+    fn probe_only_reads() {
+        let dev = MockTransport::new();
+        dev.respond(Response::Report(vec![0x04, 0x02, 0, 0, 0, 0, 0, 0]));
+        assert!(accepts_config_reports(&dev));
+        assert_eq!(dev.events().len(), 1);
+        assert!(dev.sent().is_empty());
+
+        let dev = MockTransport::new();
+        dev.respond(Response::Error("broken pipe"));
+        assert!(!accepts_config_reports(&dev));
+    }
+}
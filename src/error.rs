@@ -0,0 +1,103 @@
+use std::fmt;
+
+/// Exit code for failures that aren't one of the kinds below.
+pub const EXIT_FAILURE: i32 = 1;
+
+/// Failures scripts can tell apart by exit code. Usage errors exit with 2
+/// (that's clap), anything else with `EXIT_FAILURE`. The codes are part of
+/// the interface: don't renumber them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// No connected device matches the selection. Exit code 3.
+    DeviceNotFound(String),
+    /// The device node exists but can't be opened. Exit code 4.
+    PermissionDenied(String),
+    /// The device was found, but no interface takes the config reports.
+    /// Exit code 5.
+    WrongInterface(String),
+    /// A packet couldn't be sent or wasn't acknowledged. Exit code 6.
+    SendFailed(String),
+    /// A packet came back different from what was sent. Exit code 7.
+    ReadbackMismatch(String),
+    /// A value, profile or combination of options is invalid. Exit code 8.
+    InvalidSetting(String),
+    /// The setting exists, but not on this model or not in this tool.
+    /// Exit code 9.
+    Unsupported(String),
+}
+
+impl Error {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::DeviceNotFound(_) => 3,
+            Error::PermissionDenied(_) => 4,
+            Error::WrongInterface(_) => 5,
+            Error::SendFailed(_) => 6,
+            Error::ReadbackMismatch(_) => 7,
+            Error::InvalidSetting(_) => 8,
+            Error::Unsupported(_) => 9,
+        }
+    }
+
+    /// Name used for `kind` in JSON output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::DeviceNotFound(_) => "device_not_found",
+            Error::PermissionDenied(_) => "permission_denied",
+            Error::WrongInterface(_) => "wrong_interface",
+            Error::SendFailed(_) => "send_failed",
+            Error::ReadbackMismatch(_) => "readback_mismatch",
+            Error::InvalidSetting(_) => "invalid_setting",
+            Error::Unsupported(_) => "unsupported",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::DeviceNotFound(msg)
+            | Error::PermissionDenied(msg)
+            | Error::WrongInterface(msg)
+            | Error::SendFailed(msg)
+            | Error::ReadbackMismatch(msg)
+            | Error::InvalidSetting(msg)
+            | Error::Unsupported(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The first `Error` in `e`'s chain, so added context doesn't hide it.
+fn find(e: &anyhow::Error) -> Option<&Error> {
+    e.chain().find_map(|cause| cause.downcast_ref::<Error>())
+}
+
+pub fn exit_code(e: &anyhow::Error) -> i32 {
+    find(e).map_or(EXIT_FAILURE, Error::exit_code)
+}
+
+pub fn kind(e: &anyhow::Error) -> &'static str {
+    find(e).map_or("other", Error::kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{Context, anyhow};
+
+    #[test]
+    fn exit_code_survives_context() {
+        let e = Err::<(), _>(Error::WrongInterface("no interface".into()))
+            .context("opening 1bcf:08a0")
+            .unwrap_err();
+        assert_eq!(exit_code(&e), 5);
+        assert_eq!(kind(&e), "wrong_interface");
+        assert_eq!(format!("{e:#}"), "opening 1bcf:08a0: no interface");
+
+        let other = anyhow!("disk full");
+        assert_eq!(exit_code(&other), EXIT_FAILURE);
+        assert_eq!(kind(&other), "other");
+    }
+}
@@ -0,0 +1,144 @@
+//! Golden packet streams for CLI invocations. Each fixture in
+//! `tests/fixtures/` starts with a `# args:` line, then `# source:` lines
+//! saying where its packets came from, then the 48 expected packets; the
+//! stream produced for those args (on top of `MouseConfig::default()`) is
+//! diffed against it packet by packet.
+//!
+//! The fixtures are written by hand from the packets captured off the mouse:
+//! `COMMON_HEX` and the `DPI*`, `LED_*`, `BREATHING_SPEED_HEX` and
+//! `CONTINOUUSLY_*` tables of the original `src/main.rs`, plus its repeat and
+//! interval templates. They are never generated from the encoder under test.
+//! When one disagrees with the encoder, fix the encoder, or capture the stream
+//! again from a device and say so in the fixture's `# source:` lines.
+
+use crate::image::{self, ConfigImage, PACKET_COUNT, Packet};
+use crate::patch::ConfigPatch;
+use crate::{MouseArgs, MouseConfig};
+use clap::Parser;
+use std::{fs, path::PathBuf};
+
+const CASES: &[&str] = &[
+    "dpi 1",
+    "dpi 2",
+    "dpi 3",
+    "dpi 4",
+    "dpi 5",
+    "dpi 6",
+    "dpi 7",
+    "dpi 8",
+    "led dpi",
+    "led multi",
+    "led rainbow",
+    "led floe-light",
+    "led waltz",
+    "led four-seasons",
+    "led off",
+    "led-status enable",
+    "led-status disable",
+    "--led-brightness All",
+    "--led-brightness Half",
+    "--breathing-speed 1",
+    "--breathing-speed 2",
+    "--breathing-speed 3",
+    "--breathing-speed 4",
+    "--breathing-speed 5",
+    "--breathing-speed 6",
+    "--breathing-speed 7",
+    "--breathing-speed 8",
+    "-r 0",
+    "-r 255",
+    "-f 0",
+    "-f 255",
+    "--continously enable",
+    "--continously disable",
+    "reset",
+    "dpi 3 --breathing-speed 6",
+    "led rainbow --dpi 3",
+    "led-status disable --dpi 8 --led-brightness Half",
+    "dpi 8 -r 5 -f 255 --led-brightness Half",
+    "--continously enable -r 9",
+    "-r 9 reset",
+];
+
+fn fixture_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+fn fixture_name(args: &str) -> String {
+    let slug: Vec<&str> = args
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|s| !s.is_empty())
+        .collect();
+    format!("{}.hex", slug.join("-"))
+}
+
+fn produce(args: &str) -> ConfigImage {
+    let argv = std::iter::once("redgear-a15").chain(args.split_whitespace());
+    let args = MouseArgs::try_parse_from(argv).expect("fixture args must parse");
+    let patch = ConfigPatch::from_args(&args).expect("fixture args must not conflict");
+    ConfigImage::encode(&patch.apply(&MouseConfig::default()))
+}
+
+fn diff(expected: &[Packet], produced: &[Packet; PACKET_COUNT]) -> Vec<String> {
+    let mut out = Vec::new();
+    if expected.len() != PACKET_COUNT {
+        out.push(format!(
+            "fixture has {} packets, expected {PACKET_COUNT}",
+            expected.len()
+        ));
+    }
+    for (i, (want, got)) in expected.iter().zip(produced).enumerate() {
+        if want != got {
+            out.push(format!(
+                "packet {i:2}: expected {} got {}",
+                hex::encode(want),
+                hex::encode(got)
+            ));
+        }
+    }
+    out
+}
+
+#[test]
+fn golden_packet_streams() {
+    let mut failures = Vec::new();
+
+    for &args in CASES {
+        let path = fixture_dir().join(fixture_name(args));
+        let produced = produce(args);
+
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) => {
+                failures.push(format!("{args}: missing {}: {e}", path.display()));
+                continue;
+            }
+        };
+        let header = text.lines().next().unwrap_or_default();
+        if header != format!("# args: {args}") {
+            failures.push(format!("{args}: fixture header is '{header}'"));
+        }
+        if !text.lines().any(|line| line.starts_with("# source: ")) {
+            failures.push(format!("{args}: fixture doesn't say where it came from"));
+        }
+        let expected = image::parse_packets(&text).unwrap();
+        for line in diff(&expected, produced.packets()) {
+            failures.push(format!("{args}: {line}"));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "golden mismatches:\n{}",
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn every_fixture_has_a_case() {
+    let known: Vec<String> = CASES.iter().map(|args| fixture_name(args)).collect();
+    for entry in fs::read_dir(fixture_dir()).unwrap() {
+        let name = entry.unwrap().file_name().into_string().unwrap();
+        assert!(known.contains(&name), "stale fixture {name}");
+    }
+}
@@ -0,0 +1,173 @@
+use crate::MouseConfig;
+use crate::error::Error;
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// One successful write, as recorded in the history file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Seconds since the Unix epoch.
+    pub applied_at: u64,
+    /// The command line (or profile) that produced the config.
+    pub source: String,
+    pub config: MouseConfig,
+    /// For an `undo`, the number of the entry it went back to. The next undo
+    /// steps back from there, not from the undo itself, so repeated undos
+    /// keep going back instead of flipping between two configs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub undo_to: Option<usize>,
+}
+
+/// Which entry `undo` and `revert` go back to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pick {
+    /// The entry before the current one: the latest, or the one the latest
+    /// undo went back to.
+    Previous,
+    /// Entry `n`, counting from 1 as `history` shows them.
+    Number(usize),
+}
+
+fn history_path(dir: &Path, device: &str) -> PathBuf {
+    dir.join(format!("{device}.history.jsonl"))
+}
+
+/// Adds `entry` as a line at the end of the device's history file. Existing
+/// lines are never rewritten.
+pub fn append(dir: &Path, device: &str, entry: &Entry) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    let path = history_path(dir, device);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("opening {}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)
+        .with_context(|| format!("writing {}", path.display()))
+}
+
+/// Every entry for `device`, oldest first.
+pub fn load(dir: &Path, device: &str) -> Result<Vec<Entry>> {
+    let path = history_path(dir, device);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+    };
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("parsing {} line {}", path.display(), n + 1))
+        })
+        .collect()
+}
+
+/// The entry `pick` selects, with its number as `history` shows it.
+pub fn pick(entries: &[Entry], pick: Pick) -> Result<(usize, &Entry)> {
+    let n = match pick {
+        Pick::Previous => {
+            let current = entries
+                .last()
+                .map_or(0, |last| last.undo_to.unwrap_or(entries.len()));
+            if current < 2 {
+                bail!(Error::InvalidSetting(
+                    "nothing to undo, see `history`".into()
+                ));
+            }
+            current - 1
+        }
+        Pick::Number(n) => n,
+    };
+    match n.checked_sub(1).and_then(|i| entries.get(i)) {
+        Some(entry) => Ok((n, entry)),
+        None => bail!(Error::InvalidSetting(format!(
+            "no history entry {n}, there are {} (see `history`)",
+            entries.len()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DpiVal;
+
+    fn entry(applied_at: u64, dpi: DpiVal) -> Entry {
+        Entry {
+            applied_at,
+            source: format!("dpi {}", u8::from(dpi)),
+            config: MouseConfig {
+                dpi,
+                ..MouseConfig::default()
+            },
+            undo_to: None,
+        }
+    }
+
+    #[test]
+    fn history_is_appended_in_order() {
+        let dir = std::env::temp_dir().join(format!("redgear-a15-history-{}", std::process::id()));
+        assert!(load(&dir, "1bcf-08a0").unwrap().is_empty());
+        append(&dir, "1bcf-08a0", &entry(1, DpiVal::DPI1)).unwrap();
+        append(&dir, "1bcf-08a0", &entry(2, DpiVal::DPI5)).unwrap();
+        append(&dir, "1bcf-08a0-other", &entry(3, DpiVal::DPI8)).unwrap();
+        assert_eq!(
+            load(&dir, "1bcf-08a0").unwrap(),
+            [entry(1, DpiVal::DPI1), entry(2, DpiVal::DPI5)]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn undo_and_revert_pick_entries() {
+        let entries = [
+            entry(1, DpiVal::DPI1),
+            entry(2, DpiVal::DPI2),
+            entry(3, DpiVal::DPI3),
+        ];
+        assert_eq!(pick(&entries, Pick::Previous).unwrap().1.applied_at, 2);
+        assert_eq!(pick(&entries, Pick::Number(1)).unwrap().1.applied_at, 1);
+        for err in [
+            pick(&entries, Pick::Number(0)).unwrap_err(),
+            pick(&entries, Pick::Number(4)).unwrap_err(),
+            pick(&entries[..1], Pick::Previous).unwrap_err(),
+            pick(&[], Pick::Previous).unwrap_err(),
+        ] {
+            assert_eq!(crate::error::exit_code(&err), 8, "{err:#}");
+        }
+    }
+
+    #[test]
+    fn repeated_undo_keeps_going_back() {
+        let mut entries = vec![
+            entry(1, DpiVal::DPI1),
+            entry(2, DpiVal::DPI2),
+            entry(3, DpiVal::DPI3),
+        ];
+        let undo = |entries: &mut Vec<Entry>| {
+            let (n, picked) = pick(entries, Pick::Previous)?;
+            let undone = Entry {
+                applied_at: entries.len() as u64 + 1,
+                source: "undo".into(),
+                undo_to: Some(n),
+                ..picked.clone()
+            };
+            entries.push(undone);
+            anyhow::Ok(entries.last().unwrap().config.dpi)
+        };
+        assert_eq!(undo(&mut entries).unwrap(), DpiVal::DPI2);
+        assert_eq!(undo(&mut entries).unwrap(), DpiVal::DPI1);
+        assert!(undo(&mut entries).is_err());
+
+        // A new write after the undos is the one the next undo leaves.
+        entries.push(entry(6, DpiVal::DPI8));
+        assert_eq!(undo(&mut entries).unwrap(), DpiVal::DPI1);
+    }
+}
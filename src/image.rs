@@ -0,0 +1,849 @@
+use crate::{
+    BreathingSpeed, ContinouslyState, DpiVal, LedBrightness, LedMode, LedStatus, MouseConfig,
+};
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+use serde::Serialize;
+
+pub const PACKET_LEN: usize = 8;
+pub const PACKET_COUNT: usize = 48;
+
+pub type Packet = [u8; PACKET_LEN];
+
+pub const REPORT_ID: u8 = 0x04;
+pub const OP_DATA: u8 = 0x07;
+/// Index of the first `0x07` data packet in the image.
+pub const DATA_START: usize = 3;
+pub const DATA_COUNT: usize = 43;
+
+/// Full configuration image as captured from the official driver. Every
+/// packet is `report id (0x04)`, an opcode and six payload bytes; the data
+/// packets (`0x07`) sit between the `0401/0403/0406` prologue and the
+/// `0408/0402` epilogue.
+///
+/// The `0406` header's `0000` and `ff` look like an offset and a length, but
+/// every captured write uses these same values, even when a single setting
+/// changes, and `ff` doesn't match the 258 data bytes that follow. With
+/// nothing showing the device accepting a shorter range, writes always send
+/// the whole image.
+pub const COMMON_HEX: [&str; PACKET_COUNT] = [
+    "0401000000000000",
+    "0403000000000000",
+    "04060000ff000000",
+    "040745f80638ff00",
+    "040702040607090a",
+    "0407070104030002",
+    "04070506ff007fff",
+    "0407ffff00ff00ff",
+    "040700ff0000ffff",
+    "0407000000ffffff",
+    "0407ff00ffffff71",
+    "040701fe817e807f",
+    "0407ffffffffffff",
+    "0407feffffff0101",
+    "0407000104000102",
+    "0407000108000110",
+    "0407000500000700",
+    "0407000800000600",
+    "0407f00101000104",
+    "0407000102000108",
+    "0407000110000500",
+    "0407000700000800",
+    "0407000600f006ff",
+    "0407feffffffffff",
+    "0407fe990e05010e",
+    "040705190e05310e",
+    "040705490e05610e",
+    "040705790e05910e",
+    "040705a90e05c10e",
+    "040705d9ffffffff",
+    "0407ffffffffffff",
+    "0407feffffffffff",
+    "0407fdff00ff00ff",
+    "040700ff00ff00ff",
+    "040700ff00ff00ff",
+    "040700ff00ffffff",
+    "0407feffffffffff",
+    "0407fdffffffff00",
+    "04070000ff000000",
+    "0407ffffff00ff00",
+    "0407ff00ffffff80",
+    "040700ff008000ff",
+    "040780ffffffffff",
+    "04070afd03a1fe03",
+    "040721fe08fc94ff",
+    "0407fdfffffc94ff",
+    "0408000000000000",
+    "0402000000000000",
+];
+
+/// `COMMON_HEX` parsed at compile time, so a malformed entry is a build error
+/// instead of a panic halfway through a write.
+pub const BASE_IMAGE: [Packet; PACKET_COUNT] = packet_table(&COMMON_HEX);
+
+const fn hex_nibble(c: u8) -> u8 {
+    match c {
+        b'0'..=b'9' => c - b'0',
+        b'a'..=b'f' => c - b'a' + 10,
+        b'A'..=b'F' => c - b'A' + 10,
+        _ => panic!("packet table entry contains a non-hex digit"),
+    }
+}
+
+pub const fn packet(hex: &str) -> Packet {
+    let bytes = hex.as_bytes();
+    if bytes.len() != PACKET_LEN * 2 {
+        panic!("packet table entry must be exactly 16 hex digits");
+    }
+    let mut out = [0u8; PACKET_LEN];
+    let mut i = 0;
+    while i < PACKET_LEN {
+        out[i] = (hex_nibble(bytes[i * 2]) << 4) | hex_nibble(bytes[i * 2 + 1]);
+        i += 1;
+    }
+    if out[0] != 0x04 {
+        panic!("packet table entry must start with report id 0x04");
+    }
+    out
+}
+
+pub const fn packet_table<const N: usize>(table: &[&str; N]) -> [Packet; N] {
+    let mut out = [[0u8; PACKET_LEN]; N];
+    let mut i = 0;
+    while i < N {
+        out[i] = packet(table[i]);
+        i += 1;
+    }
+    out
+}
+
+/// Location of a setting inside the image: packet index and byte offset
+/// within that packet (the report id is byte 0).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Field {
+    pub packet: usize,
+    pub byte: usize,
+}
+
+pub const BRIGHTNESS: Field = Field { packet: 3, byte: 5 };
+pub const BRIGHTNESS_TRAILER: Field = Field {
+    packet: 10,
+    byte: 7,
+};
+/// DPI stage, followed by `DPI_CHECK`.
+pub const DPI: Field = Field {
+    packet: 11,
+    byte: 2,
+};
+/// Check byte after the DPI stage. Not a complement: see `DpiVal::bytes`.
+pub const DPI_CHECK: Field = Field {
+    packet: 11,
+    byte: 3,
+};
+/// LED control byte (breathing speed | status | mode), followed by its complement.
+pub const LED_CONTROL: Field = Field {
+    packet: 11,
+    byte: 4,
+};
+pub const REPEAT: Field = Field {
+    packet: 43,
+    byte: 4,
+};
+pub const FIRING_INTERVAL: Field = Field {
+    packet: 44,
+    byte: 4,
+};
+pub const CONTINOUSLY: Field = Field {
+    packet: 45,
+    byte: 6,
+};
+
+/// Known constant value/complement pair in the control register.
+const CONTROL_TRAILER: Field = Field {
+    packet: 11,
+    byte: 6,
+};
+
+/// Every byte owned by a decoded setting; everything else is expected to
+/// match `BASE_IMAGE`.
+const FIELDS: [Field; 11] = [
+    BRIGHTNESS,
+    BRIGHTNESS_TRAILER,
+    DPI,
+    DPI_CHECK,
+    LED_CONTROL,
+    Field {
+        packet: 11,
+        byte: 5,
+    },
+    CONTROL_TRAILER,
+    Field {
+        packet: 11,
+        byte: 7,
+    },
+    REPEAT,
+    FIRING_INTERVAL,
+    CONTINOUSLY,
+];
+
+const LED_STATUS_MASK: u8 = 0x08;
+const LED_MODE_MASK: u8 = 0x07;
+const LED_RESERVED_MASK: u8 = 0x10;
+const LED_SPEED_SHIFT: u8 = 5;
+
+/// Packet 11 (`040701fe817e807f`) is shared by four settings. The old
+/// string-replace macros each swapped the whole packet, so whichever ran last
+/// won; this keeps each setting in its own bits.
+///
+/// ```text
+/// byte 2/3  DPI stage            / check byte (`DpiVal::bytes`)
+/// byte 4/5  speed:3 0:1 off:1 mode:3 / complement
+/// byte 6/7  0x80 0x7f (constant)
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ControlRegister {
+    pub dpi: DpiVal,
+    pub led_mode: LedMode,
+    pub led_status: LedStatus,
+    pub breathing_speed: BreathingSpeed,
+}
+
+/// Settings recovered from an LED control byte; `None` for bit patterns that
+/// don't map to a known value.
+pub struct LedControl {
+    pub led_mode: Option<LedMode>,
+    pub led_status: LedStatus,
+    pub breathing_speed: Option<BreathingSpeed>,
+    pub reserved_set: bool,
+}
+
+impl ControlRegister {
+    pub fn from_config(cfg: &MouseConfig) -> Self {
+        Self {
+            dpi: cfg.dpi,
+            led_mode: cfg.led_mode,
+            led_status: cfg.led_status,
+            breathing_speed: cfg.led_args.breathing_speed.unwrap_or_default(),
+        }
+    }
+
+    pub fn led_byte(&self) -> u8 {
+        (self.breathing_speed.code() << LED_SPEED_SHIFT)
+            | self.led_status.bits()
+            | self.led_mode.code()
+    }
+
+    pub fn split_led_byte(v: u8) -> LedControl {
+        LedControl {
+            led_mode: LedMode::value_variants()
+                .iter()
+                .copied()
+                .find(|m| m.code() == v & LED_MODE_MASK),
+            led_status: if v & LED_STATUS_MASK != 0 {
+                LedStatus::Disable
+            } else {
+                LedStatus::Enable
+            },
+            breathing_speed: BreathingSpeed::ALL
+                .into_iter()
+                .find(|s| s.code() == v >> LED_SPEED_SHIFT),
+            reserved_set: v & LED_RESERVED_MASK != 0,
+        }
+    }
+
+    fn write(&self, image: &mut ConfigImage) {
+        let (stage, check) = self.dpi.bytes();
+        image.set(DPI, stage);
+        image.set(DPI_CHECK, check);
+        image.set_pair(LED_CONTROL, self.led_byte());
+    }
+}
+
+/// Repeat byte written while continuous firing is enabled.
+const REPEAT_CONTINOUS: u8 = 0xff;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigImage {
+    packets: [Packet; PACKET_COUNT],
+}
+
+impl Default for ConfigImage {
+    fn default() -> Self {
+        Self {
+            packets: BASE_IMAGE,
+        }
+    }
+}
+
+impl ConfigImage {
+    /// `encode_on` the captured A-15 image.
+    #[cfg(test)]
+    pub fn encode(cfg: &MouseConfig) -> Self {
+        Self::encode_on(&BASE_IMAGE, cfg)
+    }
+
+    /// Encodes every field of `cfg` on top of a model's base image.
+    pub fn encode_on(base: &[Packet; PACKET_COUNT], cfg: &MouseConfig) -> Self {
+        let mut image = Self { packets: *base };
+        let brightness = cfg.led_args.led_brightness.unwrap_or_default();
+        let (brgt, brgt_trailer) = brightness.bytes();
+        image.set(BRIGHTNESS, brgt);
+        image.set(BRIGHTNESS_TRAILER, brgt_trailer);
+
+        ControlRegister::from_config(cfg).write(&mut image);
+
+        let repeat = match cfg.continously {
+            ContinouslyState::Enable => REPEAT_CONTINOUS,
+            ContinouslyState::Disable => cfg.repeat,
+        };
+        image.set(REPEAT, repeat);
+        image.set(FIRING_INTERVAL, cfg.firing_interval);
+        image.set(CONTINOUSLY, cfg.continously.byte());
+        image
+    }
+
+    /// Takes a full 48-packet stream as is, or just the `0x07` data packets
+    /// with the framing filled in from `BASE_IMAGE`.
+    pub fn from_packets(packets: &[Packet]) -> Result<Self> {
+        let data_range = DATA_START..DATA_START + DATA_COUNT;
+        let mut image = Self::default();
+        match packets.len() {
+            PACKET_COUNT => image.packets.copy_from_slice(packets),
+            DATA_COUNT => image.packets[data_range.clone()].copy_from_slice(packets),
+            n => bail!("expected {PACKET_COUNT} packets or {DATA_COUNT} data packets, found {n}"),
+        }
+        for (i, pkt) in image.packets.iter().enumerate() {
+            if pkt[0] != REPORT_ID {
+                bail!(
+                    "packet {i} has report id {:02x}, expected {REPORT_ID:02x}",
+                    pkt[0]
+                );
+            }
+            if data_range.contains(&i) != (pkt[1] == OP_DATA) {
+                bail!("packet {i} has opcode {:02x} out of place", pkt[1]);
+            }
+        }
+        Ok(image)
+    }
+
+    pub fn packets(&self) -> &[Packet; PACKET_COUNT] {
+        &self.packets
+    }
+
+    pub fn get(&self, field: Field) -> u8 {
+        self.packets[field.packet][field.byte]
+    }
+
+    fn set(&mut self, field: Field, value: u8) {
+        self.packets[field.packet][field.byte] = value;
+    }
+
+    fn set_pair(&mut self, field: Field, value: u8) {
+        self.set(field, value);
+        self.packets[field.packet][field.byte + 1] = !value;
+    }
+
+    pub fn to_reports(&self) -> Vec<Vec<u8>> {
+        self.packets().iter().map(|p| p.to_vec()).collect()
+    }
+}
+
+/// Names of the settings carried by the bytes that differ between `old` and
+/// `new`, per packet. Packets that are identical are left out.
+pub fn describe_changes(old: &ConfigImage, new: &ConfigImage) -> Vec<(usize, Vec<&'static str>)> {
+    let mut out = Vec::new();
+    for (i, (a, b)) in old.packets.iter().zip(new.packets.iter()).enumerate() {
+        if a == b {
+            continue;
+        }
+        let mut names: Vec<&'static str> = Vec::new();
+        let mut push = |name| {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        };
+        for j in (0..PACKET_LEN).filter(|&j| a[j] != b[j]) {
+            let at = Field { packet: i, byte: j };
+            match at {
+                _ if at == BRIGHTNESS || at == BRIGHTNESS_TRAILER => push("LED Brightness"),
+                _ if at == DPI || at == DPI_CHECK => push("DPI"),
+                Field {
+                    packet: 11,
+                    byte: 4 | 5,
+                } => {
+                    let bits = old.get(LED_CONTROL) ^ new.get(LED_CONTROL);
+                    if bits & LED_MODE_MASK != 0 {
+                        push("LED Mode");
+                    }
+                    if bits & LED_STATUS_MASK != 0 {
+                        push("LED Status");
+                    }
+                    if bits >> LED_SPEED_SHIFT != 0 {
+                        push("LED Breathing Speed");
+                    }
+                }
+                _ if at == REPEAT => push("Repeat"),
+                _ if at == FIRING_INTERVAL => push("Firing Interval"),
+                _ if at == CONTINOUSLY => push("Continously"),
+                _ => push("unknown"),
+            }
+        }
+        out.push((i, names));
+    }
+    out
+}
+
+/// A byte outside any known field that differs from `BASE_IMAGE`, or a known
+/// field holding a value we can't map to a setting.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct UnknownByte {
+    pub packet: usize,
+    pub byte: usize,
+    pub value: u8,
+    pub expected: u8,
+}
+
+/// A value/complement pair whose second byte isn't `!value`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PairMismatch {
+    pub field: &'static str,
+    pub packet: usize,
+    pub byte: usize,
+    pub value: u8,
+    pub complement: u8,
+}
+
+#[derive(Clone, Debug)]
+pub struct Decoded {
+    /// Settings that weren't recognized keep their `MouseConfig::default()` value.
+    pub config: MouseConfig,
+    pub recognized: Vec<&'static str>,
+    pub unknown: Vec<UnknownByte>,
+    pub mismatched_pairs: Vec<PairMismatch>,
+}
+
+impl Decoded {
+    pub fn is_clean(&self) -> bool {
+        self.unknown.is_empty() && self.mismatched_pairs.is_empty()
+    }
+}
+
+/// Parses one packet per line. Blank lines and `#` comments are skipped, and
+/// only the last token of a line is used so `> SET_REPORT 0407...` logs can be
+/// fed back in as-is.
+pub fn parse_packets(text: &str) -> Result<Vec<Packet>> {
+    let mut out = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let token = line.split_whitespace().last().unwrap_or_default();
+        let bytes = match hex::decode(token) {
+            Ok(bytes) => bytes,
+            Err(e) => bail!("line {}: invalid hex '{}': {}", n + 1, token, e),
+        };
+        let Ok(pkt) = Packet::try_from(bytes.as_slice()) else {
+            bail!(
+                "line {}: expected {} bytes, got {}",
+                n + 1,
+                PACKET_LEN,
+                bytes.len()
+            );
+        };
+        out.push(pkt);
+    }
+    Ok(out)
+}
+
+/// Rebuilds a `MouseConfig` from the `0x04 0x07` data packets of an image.
+/// Framing packets (any other opcode) are ignored, so both a full 48-packet
+/// stream and the bare data packets are accepted.
+pub fn decode(packets: &[Packet]) -> Result<Decoded> {
+    let data: Vec<&Packet> = packets
+        .iter()
+        .filter(|p| p[0] == REPORT_ID && p[1] == OP_DATA)
+        .collect();
+    if data.len() != DATA_COUNT {
+        bail!("expected {} data packets, found {}", DATA_COUNT, data.len());
+    }
+
+    let mut image = ConfigImage::default();
+    for (i, pkt) in data.into_iter().enumerate() {
+        image.packets[DATA_START + i] = *pkt;
+    }
+
+    let mut out = Decoded {
+        config: MouseConfig::default(),
+        recognized: Vec::new(),
+        unknown: Vec::new(),
+        mismatched_pairs: Vec::new(),
+    };
+
+    for (i, (pkt, base)) in image.packets.iter().zip(BASE_IMAGE.iter()).enumerate() {
+        for j in 0..PACKET_LEN {
+            let owned = FIELDS.iter().any(|f| f.packet == i && f.byte == j);
+            if !owned && pkt[j] != base[j] {
+                out.unknown.push(UnknownByte {
+                    packet: i,
+                    byte: j,
+                    value: pkt[j],
+                    expected: base[j],
+                });
+            }
+        }
+    }
+
+    let brgt = LedBrightness::ALL
+        .into_iter()
+        .find(|b| b.bytes() == (image.get(BRIGHTNESS), image.get(BRIGHTNESS_TRAILER)));
+    match brgt {
+        Some(b) => {
+            out.config.led_args.led_brightness = Some(b);
+            out.recognized.push("led_brightness");
+        }
+        None => {
+            out.unknown_field(&image, BRIGHTNESS);
+            out.unknown_field(&image, BRIGHTNESS_TRAILER);
+        }
+    }
+
+    let dpi = DpiVal::ALL
+        .into_iter()
+        .find(|d| d.bytes() == (image.get(DPI), image.get(DPI_CHECK)));
+    match dpi {
+        Some(dpi) => {
+            out.config.dpi = dpi;
+            out.recognized.push("dpi");
+        }
+        None => {
+            out.unknown_field(&image, DPI);
+            out.unknown_field(&image, DPI_CHECK);
+        }
+    }
+
+    if let Some(v) = out.pair(&image, "led_control", LED_CONTROL) {
+        let led = ControlRegister::split_led_byte(v);
+        if led.reserved_set || led.led_mode.is_none() {
+            out.unknown_field(&image, LED_CONTROL);
+        }
+        if let Some(mode) = led.led_mode {
+            out.config.led_mode = mode;
+            out.recognized.push("led_mode");
+        }
+        out.config.led_status = led.led_status;
+        out.recognized.push("led_status");
+        if let Some(speed) = led.breathing_speed {
+            out.config.led_args.breathing_speed = Some(speed);
+            out.recognized.push("breathing_speed");
+        }
+    }
+
+    if let Some(v) = out.pair(&image, "control_trailer", CONTROL_TRAILER)
+        && v != BASE_IMAGE[CONTROL_TRAILER.packet][CONTROL_TRAILER.byte]
+    {
+        out.unknown_field(&image, CONTROL_TRAILER);
+    }
+
+    let cont = ContinouslyState::value_variants()
+        .iter()
+        .find(|c| c.byte() == image.get(CONTINOUSLY));
+    match cont {
+        Some(cont) => {
+            out.config.continously = *cont;
+            out.recognized.push("continously");
+        }
+        None => out.unknown_field(&image, CONTINOUSLY),
+    }
+
+    // With continuous firing on, the repeat byte is forced to 0xff and the
+    // configured count isn't stored anywhere.
+    if cont != Some(&ContinouslyState::Enable) {
+        out.config.repeat = image.get(REPEAT);
+        out.recognized.push("repeat");
+    }
+
+    out.config.firing_interval = image.get(FIRING_INTERVAL);
+    out.recognized.push("firing_interval");
+
+    Ok(out)
+}
+
+impl Decoded {
+    /// Returns the value of a pair, or records a mismatch and returns `None`.
+    fn pair(&mut self, image: &ConfigImage, name: &'static str, field: Field) -> Option<u8> {
+        let value = image.get(field);
+        let complement = image.packets[field.packet][field.byte + 1];
+        if value == !complement {
+            return Some(value);
+        }
+        self.mismatched_pairs.push(PairMismatch {
+            field: name,
+            packet: field.packet,
+            byte: field.byte,
+            value,
+            complement,
+        });
+        None
+    }
+
+    fn unknown_field(&mut self, image: &ConfigImage, field: Field) {
+        self.unknown.push(UnknownByte {
+            packet: field.packet,
+            byte: field.byte,
+            value: image.get(field),
+            expected: BASE_IMAGE[field.packet][field.byte],
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LedArgs;
+
+    fn diff(a: &ConfigImage, b: &ConfigImage) -> Vec<(usize, usize)> {
+        let mut out = Vec::new();
+        for (i, (pa, pb)) in a.packets().iter().zip(b.packets()).enumerate() {
+            for j in 0..PACKET_LEN {
+                if pa[j] != pb[j] {
+                    out.push((i, j));
+                }
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn default_config_only_touches_fire_control() {
+        let image = ConfigImage::encode(&MouseConfig::default());
+        assert_eq!(
+            diff(&image, &ConfigImage::default()),
+            vec![(FIRING_INTERVAL.packet, 4), (CONTINOUSLY.packet, 6)]
+        );
+    }
+
+    #[test]
+    fn control_register_fields_do_not_clobber_each_other() {
+        let cfg = MouseConfig {
+            dpi: DpiVal::DPI3,
+            led_mode: LedMode::Rainbow,
+            led_args: LedArgs {
+                led_brightness: Some(LedBrightness::Half),
+                breathing_speed: Some(BreathingSpeed::BS6),
+            },
+            ..MouseConfig::default()
+        };
+        let image = ConfigImage::encode(&cfg);
+        assert_eq!(image.packets()[11], packet("040702fd43bc807f"));
+        assert_eq!(image.packets()[3], packet("040745f80630ff00"));
+        assert_eq!(image.packets()[10], packet("0407ff00ffffff79"));
+    }
+
+    #[test]
+    fn control_register_round_trips_led_byte() {
+        for &led_mode in LedMode::value_variants() {
+            for led_status in [LedStatus::Enable, LedStatus::Disable] {
+                for breathing_speed in BreathingSpeed::ALL {
+                    let reg = ControlRegister {
+                        dpi: DpiVal::DPI1,
+                        led_mode,
+                        led_status,
+                        breathing_speed,
+                    };
+                    let led = ControlRegister::split_led_byte(reg.led_byte());
+                    assert_eq!(led.led_mode, Some(led_mode));
+                    assert_eq!(led.led_status, led_status);
+                    assert_eq!(led.breathing_speed, Some(breathing_speed));
+                    assert!(!led.reserved_set);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn led_disable_keeps_mode_and_speed() {
+        let cfg = MouseConfig {
+            led_status: LedStatus::Disable,
+            ..MouseConfig::default()
+        };
+        let image = ConfigImage::encode(&cfg);
+        assert_eq!(image.packets()[11], packet("040701fe8976807f"));
+    }
+
+    #[test]
+    fn continous_overrides_repeat() {
+        let cfg = MouseConfig {
+            repeat: 9,
+            continously: ContinouslyState::Enable,
+            ..MouseConfig::default()
+        };
+        let image = ConfigImage::encode(&cfg);
+        assert_eq!(image.packets()[43], packet("04070afdffa1fe03"));
+        assert_eq!(image.packets()[45], packet("0407fdfffffc64ff"));
+    }
+
+    #[test]
+    fn led_pair_is_complemented_for_every_dpi_and_speed() {
+        for dpi in DpiVal::ALL {
+            for speed in BreathingSpeed::ALL {
+                let cfg = MouseConfig {
+                    dpi,
+                    led_args: LedArgs {
+                        led_brightness: None,
+                        breathing_speed: Some(speed),
+                    },
+                    ..MouseConfig::default()
+                };
+                let p = ConfigImage::encode(&cfg).packets()[11];
+                assert_eq!(p[4], !p[5]);
+                assert_eq!(p[2], dpi.code());
+                assert_eq!(p[4] >> 5, speed.code());
+            }
+        }
+    }
+
+    #[test]
+    fn dpi_stages_match_the_captured_packets() {
+        // Packet 11 for each stage as sent by the official driver.
+        let captured = [
+            "040700ff817e807f",
+            "040701fe817e807f",
+            "040702fd817e807f",
+            "040703fd817e807f",
+            "040704fd817e807f",
+            "040705fd817e807f",
+            "040706fd817e807f",
+            "040707fd817e807f",
+        ];
+        for (dpi, hex) in DpiVal::ALL.into_iter().zip(captured) {
+            let cfg = MouseConfig {
+                dpi,
+                ..MouseConfig::default()
+            };
+            assert_eq!(
+                ConfigImage::encode(&cfg).packets()[11],
+                packet(hex),
+                "{dpi:?}"
+            );
+
+            let mut packets = BASE_IMAGE;
+            packets[11] = packet(hex);
+            let decoded = decode(&packets).unwrap();
+            assert!(decoded.mismatched_pairs.is_empty(), "{dpi:?}");
+            assert_eq!(decoded.config.dpi, dpi);
+        }
+    }
+
+    #[test]
+    fn describe_changes_names_each_setting() {
+        let old = ConfigImage::encode(&MouseConfig::default());
+        let new = ConfigImage::encode(&MouseConfig {
+            led_mode: LedMode::Waltz,
+            led_args: LedArgs {
+                led_brightness: Some(LedBrightness::Half),
+                breathing_speed: Some(BreathingSpeed::BS8),
+            },
+            continously: ContinouslyState::Enable,
+            ..MouseConfig::default()
+        });
+        assert_eq!(
+            describe_changes(&old, &new),
+            vec![
+                (3, vec!["LED Brightness"]),
+                (10, vec!["LED Brightness"]),
+                (11, vec!["LED Mode", "LED Breathing Speed"]),
+                (43, vec!["Repeat"]),
+                (45, vec!["Continously"]),
+            ]
+        );
+        assert!(describe_changes(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn decode_round_trips_every_combination() {
+        for dpi in DpiVal::ALL {
+            for &led_mode in LedMode::value_variants() {
+                for speed in BreathingSpeed::ALL {
+                    let cfg = MouseConfig {
+                        dpi,
+                        led_mode,
+                        led_status: LedStatus::Disable,
+                        led_args: LedArgs {
+                            led_brightness: Some(LedBrightness::Half),
+                            breathing_speed: Some(speed),
+                        },
+                        repeat: 0,
+                        firing_interval: 255,
+                        ..MouseConfig::default()
+                    };
+                    let decoded = decode(ConfigImage::encode(&cfg).packets()).unwrap();
+                    assert!(decoded.is_clean(), "{decoded:?}");
+                    assert_eq!(decoded.config, cfg);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn decode_reports_bad_pairs_and_unknown_bytes() {
+        let mut packets = *ConfigImage::encode(&MouseConfig::default()).packets();
+        packets[11][5] = 0x00;
+        packets[11][3] = 0xfc;
+        packets[20][7] = 0xaa;
+        let decoded = decode(&packets).unwrap();
+        assert_eq!(decoded.mismatched_pairs.len(), 1);
+        assert_eq!(decoded.mismatched_pairs[0].field, "led_control");
+        assert!(!decoded.recognized.contains(&"led_mode"));
+        assert!(!decoded.recognized.contains(&"dpi"));
+        assert_eq!(
+            decoded.unknown,
+            vec![
+                UnknownByte {
+                    packet: 20,
+                    byte: 7,
+                    value: 0xaa,
+                    expected: 0x00
+                },
+                UnknownByte {
+                    packet: 11,
+                    byte: 2,
+                    value: 0x01,
+                    expected: 0x01
+                },
+                UnknownByte {
+                    packet: 11,
+                    byte: 3,
+                    value: 0xfc,
+                    expected: 0xfe
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_accepts_bare_data_packets() {
+        let text = COMMON_HEX[DATA_START..DATA_START + DATA_COUNT].join("\n");
+        let decoded = decode(&parse_packets(&text).unwrap()).unwrap();
+        assert!(!decoded.recognized.contains(&"continously"));
+        assert_eq!(decoded.config.dpi, DpiVal::DPI2);
+        assert!(decode(&parse_packets(&text).unwrap()[1..]).is_err());
+    }
+
+    #[test]
+    fn from_packets_takes_full_or_data_only_streams() {
+        let img = ConfigImage::encode(&MouseConfig {
+            dpi: DpiVal::DPI8,
+            ..MouseConfig::default()
+        });
+        let full = ConfigImage::from_packets(img.packets()).unwrap();
+        assert_eq!(full, img);
+        let data = &img.packets()[DATA_START..DATA_START + DATA_COUNT];
+        assert_eq!(ConfigImage::from_packets(data).unwrap(), img);
+
+        assert!(ConfigImage::from_packets(&img.packets()[..10]).is_err());
+        let mut swapped = *img.packets();
+        swapped.swap(0, 5);
+        assert!(ConfigImage::from_packets(&swapped).is_err());
+    }
+}
@@ -1,9 +1,47 @@
-use anyhow::Result;
+mod backup;
+mod config;
+mod descriptor;
+mod device;
+mod error;
+#[cfg(test)]
+mod golden;
+mod history;
+mod image;
+mod model;
+mod output;
+mod pacing;
+mod patch;
+mod profile;
+mod select;
+mod session;
+mod state;
+mod transport;
+
+use anyhow::{Context, Result, anyhow, bail};
 use clap::{Args, Parser, ValueEnum};
 use core::str;
-use hex;
-use hidapi::{HidApi, HidDevice};
-use std::{process::exit, thread::sleep, time::Duration};
+use device::{
+    Verification, WriteOptions, WriteOutcome, accepts_config_reports, bytes_to_hex,
+    read_config_from_mouse, read_image, write_verified,
+};
+use error::Error;
+use hidapi::HidApi;
+use image::ConfigImage;
+use model::Model;
+use output::{Level, OutputFormat, result, say};
+use pacing::Pacing;
+use patch::ConfigPatch;
+use select::{DeviceId, Selector};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use state::State;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::exit,
+};
 
 const VID: u16 = 0x1bcf;
 const PID: u16 = 0x08a0;
@@ -14,6 +52,133 @@ pub struct MouseArgs {
     #[arg(long = "no-confirm", help = "Apply changes without confirmation")]
     pub no_confirm: bool,
 
+    #[arg(
+        long,
+        global = true,
+        help = "Print the packets that would be sent and exit without opening the device"
+    )]
+    pub dry_run: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Apply to every matching device instead of just one"
+    )]
+    pub all_devices: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = OutputFormat::Human,
+        help = "Output format; with `json` the result is printed to stdout as JSON and messages go to stderr"
+    )]
+    pub output: OutputFormat,
+
+    #[arg(
+        long,
+        global = true,
+        env = "REDGEAR_A15_DEVICE",
+        value_name = "VID:PID",
+        help = "USB vendor and product ID in hex. Default: 1bcf:08a0"
+    )]
+    pub device: Option<DeviceId>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "REDGEAR_A15_PATH",
+        help = "Use the HID device at this path, e.g. /dev/hidraw3"
+    )]
+    pub path: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "REDGEAR_A15_SERIAL",
+        help = "Use the device with this serial number"
+    )]
+    pub serial: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "REDGEAR_A15_INTERFACE",
+        help = "USB interface number to send config reports to. Default: the one declaring feature report 0x04"
+    )]
+    pub interface: Option<i32>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "REDGEAR_A15_CONFIG",
+        help = "Config file. Default: $XDG_CONFIG_HOME/redgear-a15/config.toml"
+    )]
+    pub config: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "REDGEAR_A15_RETRIES",
+        default_value_t = device::WRITE_RETRIES,
+        help = "Times to repeat a write that fails or reads back wrong before restoring the last known-good config"
+    )]
+    pub retries: usize,
+
+    #[arg(
+        long,
+        global = true,
+        env = "REDGEAR_A15_PACKET_DELAY",
+        value_name = "MS",
+        help = "Wait after each packet before polling for its acknowledgement. Default: from `calibrate`, else 5"
+    )]
+    pub packet_delay: Option<u64>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "REDGEAR_A15_ACK_TIMEOUT",
+        value_name = "MS",
+        help = "How long to poll for a packet's acknowledgement before resending it. Default: 300"
+    )]
+    pub ack_timeout: Option<u64>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "REDGEAR_A15_PACKET_RETRIES",
+        value_name = "N",
+        help = "Times to resend a begin or mode packet that isn't acknowledged. Default: 2"
+    )]
+    pub packet_retries: Option<usize>,
+
+    #[arg(
+        short,
+        long,
+        global = true,
+        action = clap::ArgAction::Count,
+        help = "Show every report sent and received; twice adds timestamps and latencies"
+    )]
+    pub verbose: u8,
+
+    #[arg(
+        short,
+        long,
+        global = true,
+        conflicts_with = "verbose",
+        help = "Print only the final result"
+    )]
+    pub quiet: bool,
+
+    #[arg(
+        long,
+        global = true,
+        env = "REDGEAR_A15_TRACE_FILE",
+        value_name = "FILE",
+        help = "Write a timestamped trace of every report sent and received to this file"
+    )]
+    pub trace_file: Option<PathBuf>,
+
     #[command(flatten)]
     pub fire_control: Option<FireControl>,
 
@@ -69,7 +234,7 @@ pub struct FireControl {
     pub continously: Option<ContinouslyState>,
 }
 
-#[derive(Debug, Clone, Parser)]
+#[derive(Debug, Clone, PartialEq, Eq, Parser, Serialize, Deserialize)]
 pub struct LedArgs {
     #[arg(long, help = "LED brightness (All/Half). Default: All")]
     pub led_brightness: Option<LedBrightness>,
@@ -133,6 +298,9 @@ pub enum Commands {
 
         #[arg(value_enum)]
         mode: LedMode,
+
+        #[arg(long, help = "Also set the DPI level (1–8)")]
+        dpi: Option<DpiVal>,
     },
 
     /// Enable or disable LED lights
@@ -141,11 +309,108 @@ pub enum Commands {
         opts: GlobalMouseOptions,
 
         state: LedStatus,
+
+        #[arg(long, help = "Also set the DPI level (1–8)")]
+        dpi: Option<DpiVal>,
     },
 
     #[command(about = "Reset all mouse settings to their default values")]
     Reset,
+
+    /// Decode a captured or saved packet stream (one hex packet per line, `-` for stdin)
+    Decode { file: PathBuf },
+
+    /// Read the current configuration from the mouse (falls back to the saved state)
+    #[command(alias = "get")]
+    Status,
+
+    /// List connected mice and their HID interfaces
+    List,
+
+    /// Apply a TOML or JSON profile file (options given on the command line win)
+    Apply {
+        #[command(flatten)]
+        opts: GlobalMouseOptions,
+
+        file: PathBuf,
+    },
+
+    /// Manage named profiles in $XDG_CONFIG_HOME/redgear-a15/profiles
+    Profile {
+        #[command(subcommand)]
+        action: ProfileCommand,
+    },
+
+    /// Save the complete device image (read back, or the saved state) with device identity
+    Backup {
+        /// Output file. Default: $XDG_STATE_HOME/redgear-a15/backups/<device>-<time>.json
+        file: Option<PathBuf>,
+    },
+
+    /// Write a backup to the device byte for byte
+    Restore {
+        file: PathBuf,
+
+        #[arg(
+            long,
+            help = "Restore even if the backup was taken from a different VID/PID"
+        )]
+        force: bool,
+    },
+
+    /// Show every configuration applied to the device, oldest first
+    History,
+
+    /// Re-apply the configuration from before the last change
+    Undo,
+
+    /// Re-apply entry <N> from `history`
+    Revert { n: usize },
+
+    /// Write the current configuration (read back, or the saved state) as a profile
+    Export {
+        /// Output file, stdout when left out
+        file: Option<PathBuf>,
+
+        #[arg(
+            long,
+            value_enum,
+            help = "Profile format. Default: from the file extension, else toml"
+        )]
+        format: Option<profile::Format>,
+    },
+
+    /// Find the fastest packet pacing the mouse handles reliably and remember it
+    Calibrate {
+        #[arg(long, default_value_t = 3, help = "Writes per candidate delay")]
+        rounds: usize,
+    },
 }
+#[derive(Debug, Clone, Parser)]
+pub enum ProfileCommand {
+    /// Save the current configuration (read back, or the saved state) under a name
+    Save {
+        name: String,
+
+        #[arg(long, help = "Replace an existing profile with the same name")]
+        force: bool,
+    },
+
+    /// Apply a saved profile (options given on the command line win)
+    Load {
+        #[command(flatten)]
+        opts: GlobalMouseOptions,
+
+        name: String,
+    },
+
+    /// List saved profiles
+    List,
+
+    /// Delete a saved profile
+    Delete { name: String },
+}
+
 pub enum Reset {
     RepeatVal(u8),
     FiringInterval(u8),
@@ -170,28 +435,32 @@ pub fn reset_val() -> Vec<Reset> {
     ]
 }
 
-#[derive(ValueEnum, Clone, Debug)]
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ContinouslyState {
     Enable,
     Disable,
 }
 
 impl ContinouslyState {
-    pub fn hex(&self) -> &'static str {
+    pub fn byte(&self) -> u8 {
         match self {
             ContinouslyState::Enable => CONTINOUUSLY_ENABLED,
             ContinouslyState::Disable => CONTINOUUSLY_DISABLED,
         }
     }
 }
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum LedBrightness {
+    #[default]
     All,
     Half,
 }
 
 impl LedBrightness {
-    pub fn hex(&self) -> (&'static str, &'static str) {
+    pub const ALL: [LedBrightness; 2] = [LedBrightness::All, LedBrightness::Half];
+
+    pub fn bytes(&self) -> (u8, u8) {
         match self {
             LedBrightness::All => LED_BRGT_FULL,
             LedBrightness::Half => LED_BRGT_HALF,
@@ -210,14 +479,15 @@ impl std::str::FromStr for LedBrightness {
         }
     }
 }
-#[derive(ValueEnum, Clone, Debug)]
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum LedStatus {
     Enable,
     Disable,
 }
 
 impl LedStatus {
-    pub fn hex(&self) -> &'static str {
+    pub fn bits(&self) -> u8 {
         match self {
             LedStatus::Enable => LED_ENABLE,
             LedStatus::Disable => LED_DISABLE,
@@ -225,7 +495,7 @@ impl LedStatus {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct MouseConfig {
     pub repeat: u8,
     pub firing_interval: u8,
@@ -237,6 +507,9 @@ pub struct MouseConfig {
     pub led_status: LedStatus,
     pub dpi: DpiVal,
     pub led_mode: LedMode,
+    /// A one-off request to restore the factory values, never saved: a
+    /// state or history entry written after a reset must not reset again.
+    #[serde(skip)]
     pub reset: bool,
 }
 
@@ -261,7 +534,31 @@ impl Default for MouseConfig {
     }
 }
 
-#[derive(Clone, Debug)]
+impl MouseConfig {
+    /// The values `reset` restores, see `reset_val()`.
+    pub fn factory() -> Self {
+        let mut cfg = Self {
+            reset: true,
+            ..Self::default()
+        };
+        for val in reset_val() {
+            match val {
+                Reset::RepeatVal(repeat) => cfg.repeat = repeat,
+                Reset::FiringInterval(interval) => cfg.firing_interval = interval,
+                Reset::Continously(state) => cfg.continously = state,
+                Reset::DpiVal(dpi) => cfg.dpi = dpi,
+                Reset::LedStatus(status) => cfg.led_status = status,
+                Reset::LedBrightness(brgt) => cfg.led_args.led_brightness = Some(brgt),
+                Reset::LedMode(mode) => cfg.led_mode = mode,
+                Reset::BreathingSpeed(speed) => cfg.led_args.breathing_speed = Some(speed),
+            }
+        }
+        cfg
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "u8", into = "u8")]
 pub enum DpiVal {
     DPI1,
     DPI2,
@@ -274,18 +571,54 @@ pub enum DpiVal {
 }
 
 impl DpiVal {
-    pub fn hex(&self) -> &'static str {
+    pub const ALL: [DpiVal; 8] = [
+        DpiVal::DPI1,
+        DpiVal::DPI2,
+        DpiVal::DPI3,
+        DpiVal::DPI4,
+        DpiVal::DPI5,
+        DpiVal::DPI6,
+        DpiVal::DPI7,
+        DpiVal::DPI8,
+    ];
+
+    /// Stage index written to the DPI byte (1000 DPI = 0 ... 8000 DPI = 7).
+    pub fn code(&self) -> u8 {
         match self {
-            DpiVal::DPI1 => DPI1,
-            DpiVal::DPI2 => DPI2,
-            DpiVal::DPI3 => DPI3,
-            DpiVal::DPI4 => DPI4,
-            DpiVal::DPI5 => DPI5,
-            DpiVal::DPI6 => DPI6,
-            DpiVal::DPI7 => DPI7,
-            DpiVal::DPI8 => DPI8,
+            DpiVal::DPI1 => 0,
+            DpiVal::DPI2 => 1,
+            DpiVal::DPI3 => 2,
+            DpiVal::DPI4 => 3,
+            DpiVal::DPI5 => 4,
+            DpiVal::DPI6 => 5,
+            DpiVal::DPI7 => 6,
+            DpiVal::DPI8 => 7,
         }
     }
+
+    /// Stage byte and the check byte after it, as captured from the official
+    /// driver. The check byte is the complement for stages 1–3 only; from
+    /// stage 4 up it stays `fd`.
+    pub fn bytes(&self) -> (u8, u8) {
+        let code = self.code();
+        (code, DPI_CHECK[usize::from(code)])
+    }
+}
+
+impl TryFrom<u8> for DpiVal {
+    type Error = &'static str;
+    fn try_from(level: u8) -> std::result::Result<Self, Self::Error> {
+        DpiVal::ALL
+            .get(usize::from(level).wrapping_sub(1))
+            .copied()
+            .ok_or("DPI level must be 1–8")
+    }
+}
+
+impl From<DpiVal> for u8 {
+    fn from(dpi: DpiVal) -> u8 {
+        dpi.code() + 1
+    }
 }
 
 impl str::FromStr for DpiVal {
@@ -305,7 +638,8 @@ impl str::FromStr for DpiVal {
     }
 }
 
-#[derive(ValueEnum, Clone, Debug)]
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum LedMode {
     Dpi,
     Multi,
@@ -317,204 +651,42 @@ pub enum LedMode {
 }
 
 impl LedMode {
-    pub fn hex(&self) -> &'static str {
+    /// Low three bits of the LED control byte.
+    pub fn code(&self) -> u8 {
         match self {
+            LedMode::Dpi => LED_MODE_DPI,
             LedMode::Multi => LED_MODE_MULTI,
             LedMode::Rainbow => LED_MODE_RAINBOW,
             LedMode::FloeLight => LED_MODE_FLOE_LIGHT,
             LedMode::Waltz => LED_MODE_WALTZ,
             LedMode::FourSeasons => LED_MODE_FOUR_SEASONS,
-            LedMode::Dpi => LED_MODE_DPI,
             LedMode::Off => LED_MODE_OFF,
         }
     }
 }
 
-const DPI1: &str = "040700ff817e807f"; // 1000
-const DPI2: &str = "040701fe817e807f"; // 1600
-const DPI3: &str = "040702fd817e807f"; // 2400
-const DPI4: &str = "040703fd817e807f"; // 3200
-const DPI5: &str = "040704fd817e807f"; // 4800
-const DPI6: &str = "040705fd817e807f"; // 6400
-const DPI7: &str = "040706fd817e807f"; // 7200
-const DPI8: &str = "040707fd817e807f"; // 8000
-
-const CONTINOUUSLY_DISABLED: &str = "0407fdfffffc1bff";
-const CONTINOUUSLY_ENABLED: &str = "0407fdfffffc64ff"; // Repeat shall be disabled - 04070afdffa1fe03
-const LED_DISABLE: &str = "040701fe8976807f";
-const LED_ENABLE: &str = "040701fe817e807f";
-const LED_MODE_MULTI: &str = "040701fe827d807f";
-const LED_MODE_RAINBOW: &str = "040701fe837c807f";
-const LED_MODE_FLOE_LIGHT: &str = "040701fe847b807f";
-const LED_MODE_WALTZ: &str = "040701fe857a807f";
-const LED_MODE_FOUR_SEASONS: &str = "040701fe8679807f";
-const LED_MODE_DPI: &str = "040701fe817e807f";
-const LED_MODE_OFF: &str = "040701fe8778807f";
-const LED_BRGT_FULL: (&str, &str) = ("040745f80638ff00", "0407ff00ffffff71");
-const LED_BRGT_HALF: (&str, &str) = ("040745f80630ff00", "0407ff00ffffff79");
-
-// Yes.. these macros could just be functions.
-macro_rules! generate_hex_val_for_repeat {
-    (
-        $REPEAT_REQ: expr,
-        $FULL_HEX: expr
-    ) => {{
-        let hex_val = "04070afd03a1fe03";
-        let repeat_req_in_hex = hex::encode([$REPEAT_REQ]);
-        let final_val = hex_val.replace("fd03", format!("fd{}", repeat_req_in_hex).as_str());
-        let final_hex: Vec<String> = $FULL_HEX
-            .iter()
-            .map(|&x| x.replace(hex_val, final_val.as_str()))
-            .collect();
-        final_hex
-    }};
-}
-
-macro_rules! generate_hex_for_interval {
-    (
-        $REPEAT_REQ: expr,
-        $FULL_HEX: expr
-    ) => {{
-        let hex_val = "040721fe08fc94ff";
-        let firing_interval_req_in_hex = hex::encode([$REPEAT_REQ]);
-        let final_val =
-            hex_val.replace("fe08", format!("fe{}", firing_interval_req_in_hex).as_str());
-        let final_hex: Vec<String> = $FULL_HEX
-            .iter()
-            .map(|x| x.replace(hex_val, final_val.as_str()))
-            .collect();
-        final_hex
-    }};
-}
-
-macro_rules! gen_hex_for_led {
-    ($MODE:expr, $FULL_HEX:expr) => {{
-        let mode_hex = $MODE.hex();
-        let output: Vec<String> = $FULL_HEX
-            .iter()
-            .map(|x| x.replace("040701fe817e807f", mode_hex))
-            .collect();
-        output
-    }};
-}
-
-macro_rules! gen_hex_for_dpi {
-    (
-        $MODE: expr,
-        $FULL_HEX: expr
-    ) => {{
-        let mod_hex = $MODE.hex();
-        let output: Vec<String> = $FULL_HEX
-            .iter()
-            .map(|x| x.replace("040701fe817e807f", mod_hex))
-            .collect();
-        output
-    }};
-}
-
-macro_rules! gen_hex_for_led_brgt {
-    (
-        $MODE: expr,
-        $FULL_HEX: expr
-    ) => {{
-        let (first_hex, second_hex) = $MODE.hex();
-        let output: Vec<String> = $FULL_HEX
-            .iter()
-            .map(|x| x.replace("040745f80638ff00", first_hex))
-            .map(|y| y.replace("0407ff00ffffff71", second_hex))
-            .collect();
-        output
-    }};
-}
-
-macro_rules! gen_hex_for_breathing_speed {
-    (
-        $MODE: expr,
-        $FULL_HEX: expr
-    ) => {{
-        let breathing_hex = $MODE.hex();
-        let output: Vec<String> = $FULL_HEX
-            .iter()
-            .map(|x| x.replace("040701fe817e807f", breathing_hex))
-            .collect();
-        output
-    }};
-}
-
-macro_rules! gen_hex_for_continously {
-    (
-        $MODE: expr,
-        $FULL_HEX: expr
-    ) => {{
-        let continously_hex = $MODE.hex();
-        if continously_hex == "0407fdfffffc64ff" {
-            let output: Vec<String> = $FULL_HEX
-                .iter()
-                .map(|x| x.replace("0407fdfffffc94ff", continously_hex))
-                .map(|y| y.replace("04070afd03a1fe03", "04070afdffa1fe03"))
-                .collect();
-            output
-        } else {
-            let output: Vec<String> = $FULL_HEX
-                .iter()
-                .map(|x| x.replace("0407fdfffffc94ff", continously_hex))
-                .collect();
-            output
-        }
-    }};
-}
-
-fn convert_str_hex(hex: &str) -> Vec<u8> {
-    hex.as_bytes()
-        .chunks(2)
-        .map(|parts| {
-            let hex_str = std::str::from_utf8(parts).unwrap();
-            u8::from_str_radix(hex_str, 16).unwrap()
-        })
-        .collect()
-}
-
-fn bytes_to_hex(data: &[u8]) -> String {
-    data.iter().map(|b| format!("{:02X}", b)).collect()
-}
-
-fn send_report_to_mouse(packets: Vec<Vec<u8>>, dev: HidDevice) -> Result<()> {
-    println!("> Sending feature reports...");
-    for pkts in &packets {
-        println!("> SET_REPORT {}", bytes_to_hex(pkts));
-        if let Err(e) = dev.send_feature_report(pkts) {
-            eprintln!("FATAL: Failed to send report: {e}");
-            break;
-        }
-
-        sleep(Duration::from_millis(300));
-
-        let mut report_id = pkts.clone();
-        if let Err(e) = dev.get_feature_report(&mut report_id) {
-            eprintln!("WARN: Failed to read report: {e}");
-        } else {
-            println!("< GET_REPORT {}", bytes_to_hex(&report_id));
-        }
-    }
-    Ok(())
-}
-
-const BREATHING_SPEED_HEX: [&str; 8] = [
-    "040701fee11e807f",
-    "040701fec13e807f",
-    "040701fea15e807f",
-    "040701fe817e807f",
-    "1040701fe619e807f",
-    "040701fe41be807f",
-    "040701fe21de807f",
-    "040701fe01fe807f",
-];
-
-#[derive(Clone, Debug)]
+const CONTINOUUSLY_DISABLED: u8 = 0x1b;
+const CONTINOUUSLY_ENABLED: u8 = 0x64; // Repeat shall be disabled - 04070afdffa1fe03
+const LED_ENABLE: u8 = 0x00;
+const LED_DISABLE: u8 = 0x08;
+const LED_MODE_DPI: u8 = 0x01;
+const LED_MODE_MULTI: u8 = 0x02;
+const LED_MODE_RAINBOW: u8 = 0x03;
+const LED_MODE_FLOE_LIGHT: u8 = 0x04;
+const LED_MODE_WALTZ: u8 = 0x05;
+const LED_MODE_FOUR_SEASONS: u8 = 0x06;
+const LED_MODE_OFF: u8 = 0x07;
+const LED_BRGT_FULL: (u8, u8) = (0x38, 0x71);
+const LED_BRGT_HALF: (u8, u8) = (0x30, 0x79);
+const DPI_CHECK: [u8; 8] = [0xff, 0xfe, 0xfd, 0xfd, 0xfd, 0xfd, 0xfd, 0xfd];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "u8", into = "u8")]
 pub enum BreathingSpeed {
     BS1,
     BS2,
     BS3,
+    #[default]
     BS4,
     BS5,
     BS6,
@@ -522,19 +694,48 @@ pub enum BreathingSpeed {
     BS8,
 }
 impl BreathingSpeed {
-    pub fn hex(&self) -> &'static str {
+    pub const ALL: [BreathingSpeed; 8] = [
+        BreathingSpeed::BS1,
+        BreathingSpeed::BS2,
+        BreathingSpeed::BS3,
+        BreathingSpeed::BS4,
+        BreathingSpeed::BS5,
+        BreathingSpeed::BS6,
+        BreathingSpeed::BS7,
+        BreathingSpeed::BS8,
+    ];
+
+    /// High three bits of the LED control byte; the device counts down, so
+    /// the fastest speed (8) is 0.
+    pub fn code(&self) -> u8 {
         match self {
-            BreathingSpeed::BS1 => BREATHING_SPEED_HEX[0],
-            BreathingSpeed::BS2 => BREATHING_SPEED_HEX[1],
-            BreathingSpeed::BS3 => BREATHING_SPEED_HEX[2],
-            BreathingSpeed::BS4 => BREATHING_SPEED_HEX[3],
-            BreathingSpeed::BS5 => BREATHING_SPEED_HEX[4],
-            BreathingSpeed::BS6 => BREATHING_SPEED_HEX[5],
-            BreathingSpeed::BS7 => BREATHING_SPEED_HEX[6],
-            BreathingSpeed::BS8 => BREATHING_SPEED_HEX[7],
+            BreathingSpeed::BS1 => 7,
+            BreathingSpeed::BS2 => 6,
+            BreathingSpeed::BS3 => 5,
+            BreathingSpeed::BS4 => 4,
+            BreathingSpeed::BS5 => 3,
+            BreathingSpeed::BS6 => 2,
+            BreathingSpeed::BS7 => 1,
+            BreathingSpeed::BS8 => 0,
         }
     }
 }
+impl TryFrom<u8> for BreathingSpeed {
+    type Error = &'static str;
+    fn try_from(level: u8) -> std::result::Result<Self, Self::Error> {
+        BreathingSpeed::ALL
+            .get(usize::from(level).wrapping_sub(1))
+            .copied()
+            .ok_or("breathing speed must be 1–8")
+    }
+}
+
+impl From<BreathingSpeed> for u8 {
+    fn from(speed: BreathingSpeed) -> u8 {
+        8 - speed.code()
+    }
+}
+
 impl std::str::FromStr for BreathingSpeed {
     type Err = &'static str;
 
@@ -553,57 +754,6 @@ impl std::str::FromStr for BreathingSpeed {
     }
 }
 
-const COMMON_HEX: [&str; 48] = [
-    "0401000000000000",
-    "0403000000000000",
-    "04060000ff000000",
-    "040745f80638ff00",
-    "040702040607090a",
-    "0407070104030002",
-    "04070506ff007fff",
-    "0407ffff00ff00ff",
-    "040700ff0000ffff",
-    "0407000000ffffff",
-    "0407ff00ffffff71",
-    "040701fe817e807f",
-    "0407ffffffffffff",
-    "0407feffffff0101",
-    "0407000104000102",
-    "0407000108000110",
-    "0407000500000700",
-    "0407000800000600",
-    "0407f00101000104",
-    "0407000102000108",
-    "0407000110000500",
-    "0407000700000800",
-    "0407000600f006ff",
-    "0407feffffffffff",
-    "0407fe990e05010e",
-    "040705190e05310e",
-    "040705490e05610e",
-    "040705790e05910e",
-    "040705a90e05c10e",
-    "040705d9ffffffff",
-    "0407ffffffffffff",
-    "0407feffffffffff",
-    "0407fdff00ff00ff",
-    "040700ff00ff00ff",
-    "040700ff00ff00ff",
-    "040700ff00ffffff",
-    "0407feffffffffff",
-    "0407fdffffffff00",
-    "04070000ff000000",
-    "0407ffffff00ff00",
-    "0407ff00ffffff80",
-    "040700ff008000ff",
-    "040780ffffffffff",
-    "04070afd03a1fe03",
-    "040721fe08fc94ff",
-    "0407fdfffffc94ff",
-    "0408000000000000",
-    "0402000000000000",
-];
-
 const BOLD: &str = "\x1b[1m";
 const DIM: &str = "\x1b[2m";
 const RED: &str = "\x1b[31m";
@@ -612,260 +762,1324 @@ const YELLOW: &str = "\x1b[33m";
 const CYAN: &str = "\x1b[36m";
 const RESET: &str = "\x1b[0m";
 
-fn main() -> Result<()> {
-    use std::io::{self, Write};
-    let args = MouseArgs::parse();
-    let default_val = MouseConfig::default();
-    let mut repeat = default_val.repeat;
-    let mut firing_interval = default_val.firing_interval;
-    let led_args = default_val.led_args;
-    let led_brightness = led_args.led_brightness.unwrap();
-    let breathing_speed = led_args.breathing_speed.unwrap();
-    let mut changes: Vec<(String, String)> = Vec::new();
-    let mut continously = default_val.continously;
+/// Every HID interface with the selected VID/PID or the same vendor ID, one
+/// unit at a time. For each interface the report descriptor is checked for
+/// feature report 0x04, and the interface is asked for it (read-only), to
+/// show which one config packets go to.
+fn list_devices(sel: &Selector) -> Result<()> {
+    let api = HidApi::new()?;
+    let mut found: Vec<select::Candidate> = api
+        .device_list()
+        .map(select::Candidate::from_info)
+        .filter(|c| c.id.vid == sel.id.vid || sel.path.as_ref() == Some(&c.path))
+        .collect();
+    found.sort_by(|a, b| {
+        let key = |c: &select::Candidate| (c.id.vid, c.id.pid, c.serial.clone(), c.interface);
+        key(a).cmp(&key(b)).then_with(|| a.path.cmp(&b.path))
+    });
+    let reports: HashMap<&str, _> = found
+        .iter()
+        .map(|c| (c.path.as_str(), select::feature_reports(&api, c)))
+        .collect();
+    let selected = select::choose(&found, sel)
+        .ok()
+        .and_then(|(unit, _)| {
+            if sel.path.is_some() {
+                return Some(unit[0]);
+            }
+            let unit_reports: Vec<_> = unit
+                .iter()
+                .map(|c| reports[c.path.as_str()].clone())
+                .collect();
+            select::pick_interface(&unit, &unit_reports, sel.interface).ok()
+        })
+        .map(|c| c.path.clone());
 
-    if let Some(fc) = args.fire_control.as_ref() {
-        if let Some(rep) = fc.repeat {
-            changes.push(("Repeat".into(), rep.to_string()));
-            repeat = rep;
-        }
-        if let Some(intv) = fc.firing_interval {
-            changes.push(("Firing Interval".into(), format!("{}", intv)));
-            firing_interval = intv;
-        }
-        if let Some(cont) = &fc.continously {
-            changes.push(("Continously".into(), format!("{:?}", cont)));
-            continously = cont.to_owned();
+    result!("\n{BOLD}{CYAN}Devices{RESET}");
+    result!("{DIM}──────────────────────────────────────────{RESET}");
+    if found.is_empty() {
+        result!("  No devices with vendor ID {:04x} found.", sel.id.vid);
+    }
+    let mut devices = Vec::new();
+    for unit in select::units(&found) {
+        let first = unit[0];
+        let model = model::lookup(
+            first.id,
+            first.manufacturer.as_deref(),
+            first.product.as_deref(),
+        );
+        result!(
+            "{BOLD}{}{RESET}{}  {}  {} / {}  serial {}{}",
+            first.id,
+            if first.id == sel.id {
+                ""
+            } else {
+                " (same vendor)"
+            },
+            model.map_or("unknown model", |m| m.name),
+            first.manufacturer.as_deref().unwrap_or("-"),
+            first.product.as_deref().unwrap_or("-"),
+            first.serial.as_deref().unwrap_or("-"),
+            first
+                .unit
+                .as_ref()
+                .map_or(String::new(), |u| format!("  port {u}")),
+        );
+        for c in unit {
+            let declared = &reports[c.path.as_str()];
+            let probe = api
+                .open_path(&std::ffi::CString::new(c.path.as_str())?)
+                .map(|dev| accepts_config_reports(&dev))
+                .map_err(|e| e.to_string());
+            let is_selected = selected.as_ref() == Some(&c.path);
+
+            let declares = match declared {
+                Ok(ids) if ids.contains(&image::REPORT_ID) => {
+                    format!("{GREEN}declares feature report 0x04{RESET}")
+                }
+                Ok(_) => format!("{DIM}no feature report 0x04 declared{RESET}"),
+                Err(e) => format!("{YELLOW}descriptor not readable: {e}{RESET}"),
+            };
+            let answers = match &probe {
+                Ok(true) => format!("{GREEN}answers 0x04{RESET}"),
+                Ok(false) => format!("{DIM}doesn't answer 0x04{RESET}"),
+                Err(_) => format!("{YELLOW}not probed{RESET}"),
+            };
+            result!(
+                "  interface {}  usage {:04x}:{:04x}  {}  {declares}, {answers}{}",
+                c.interface,
+                c.usage_page,
+                c.usage,
+                c.path,
+                if is_selected { "  (selected)" } else { "" }
+            );
+
+            devices.push(json!({
+                "vid": c.id.vid,
+                "pid": c.id.pid,
+                "manufacturer": c.manufacturer,
+                "product": c.product,
+                "serial": c.serial,
+                "interface": c.interface,
+                "usage_page": c.usage_page,
+                "usage": c.usage,
+                "path": c.path,
+                "unit": c.unit,
+                "model": model.map(|m| m.name),
+                "feature_reports": declared.as_ref().ok(),
+                "descriptor_error": declared.as_ref().err(),
+                "accepts_config_reports": probe.as_ref().ok(),
+                "probe_error": probe.as_ref().err(),
+                "selected": is_selected,
+            }));
         }
     }
+    result!("{DIM}──────────────────────────────────────────{RESET}");
+    output::emit(json!({
+        "command": "list",
+        "ok": true,
+        "devices": devices,
+    }));
+    Ok(())
+}
+
+/// Where `read_current` got the configuration from.
+enum Source {
+    Device,
+    Cache { applied_at: u64 },
+}
+
+struct Current {
+    device: String,
+    config: MouseConfig,
+    source: Source,
+}
 
-    if let Some(led) = args.led_args.as_ref() {
-        if let Some(br) = &led.led_brightness {
-            changes.push(("LED Brightness".into(), format!("{:?}", br)));
+/// The configuration on the mouse, read back with GET_REPORT, or the saved
+/// state from the last successful write when readback isn't possible or
+/// doesn't decode cleanly.
+fn read_current(sel: &Selector) -> Result<Current> {
+    let mut device = state::device_key(sel.id.vid, sel.id.pid, sel.serial.as_deref());
+    let readback = match HidApi::new()
+        .map_err(anyhow::Error::from)
+        .and_then(|api| select::open(&api, sel))
+    {
+        Ok((dev, _, key)) => {
+            device = key;
+            read_config_from_mouse(&dev).and_then(|packets| image::decode(&packets))
         }
-        if let Some(bs) = &led.breathing_speed {
-            changes.push(("LED Breathing Speed".into(), format!("{:?}", bs)));
+        Err(e) => Err(e),
+    };
+
+    match readback {
+        Ok(decoded) if decoded.is_clean() => {
+            return Ok(Current {
+                device,
+                config: decoded.config,
+                source: Source::Device,
+            });
         }
+        Ok(_) => eprintln!(
+            "{YELLOW}WARN:{RESET} Readback didn't decode cleanly, see `decode` for details"
+        ),
+        Err(e) => eprintln!("{YELLOW}WARN:{RESET} Readback not possible: {e:#}"),
     }
 
-    if let Some(cmd) = args.command.as_ref() {
-        match cmd {
-            Commands::Dpi { dpi_val, .. } => {
-                changes.push(("DPI".into(), format!("{:?}", dpi_val)));
-            }
-            Commands::Led { mode, .. } => {
-                changes.push(("LED Mode".into(), format!("{:?}", mode)));
-            }
-            Commands::LedStatus { state, .. } => {
-                changes.push(("LED Status".into(), format!("{:?}", state)));
-            }
-            Commands::Reset => {
-                changes.push(("Reset".into(), "Factory Defaults".into()));
-            }
+    let Some(saved) = state::load(&state::state_dir()?, &device)? else {
+        bail!("No saved state for {device} either.");
+    };
+    Ok(Current {
+        device,
+        config: saved.config,
+        source: Source::Cache {
+            applied_at: saved.applied_at,
+        },
+    })
+}
+
+fn show_status(sel: &Selector) -> Result<()> {
+    let current = read_current(sel)?;
+    let mut doc = json!({
+        "command": "status",
+        "ok": true,
+        "device": current.device,
+        "config": current.config,
+    });
+    match current.source {
+        Source::Device => {
+            result!("\n{BOLD}{CYAN}Status{RESET} {DIM}(read from device){RESET}");
+            doc["source"] = json!("device");
+        }
+        Source::Cache { applied_at } => {
+            result!(
+                "\n{BOLD}{CYAN}Status{RESET} {YELLOW}(cached: last applied {}){RESET}",
+                state::describe_age(applied_at)
+            );
+            doc["source"] = json!("cache");
+            doc["applied_at"] = json!(applied_at);
         }
     }
+    result!("{DIM}──────────────────────────────────────────{RESET}");
+    print_config(&current.config, Level::Quiet);
+    result!("{DIM}──────────────────────────────────────────{RESET}");
+    output::emit(doc);
+    Ok(())
+}
 
-    if args.moving_speed.is_some() {
+fn export_profile(
+    sel: &Selector,
+    file: Option<&Path>,
+    format: Option<profile::Format>,
+) -> Result<()> {
+    let current = read_current(sel)?;
+    if let Source::Cache { applied_at } = current.source {
         eprintln!(
-            "{RED}{BOLD}Error:{RESET} Changing 'moving_speed' is not implemented. See notes on GitHub - https://github.com/vamsi200/Redgear-A15/tree/main#some-notes."
+            "{YELLOW}WARN:{RESET} Exporting the saved state (last applied {})",
+            state::describe_age(applied_at)
         );
-        std::process::exit(1);
     }
+    let profile = profile::Profile::from_config(&current.config);
+    let format = format
+        .or(file.map(profile::Format::from_path))
+        .unwrap_or_default();
+    let text = profile.render(format)?;
+    match file {
+        Some(file) => {
+            fs::write(file, &text).with_context(|| format!("writing {}", file.display()))?;
+            result!("> Profile written to {}", file.display());
+        }
+        None if !output::is_json() => print!("{text}"),
+        None => {}
+    }
+    output::emit(json!({
+        "command": "export",
+        "ok": true,
+        "device": current.device,
+        "file": file,
+        "profile": profile,
+    }));
+    Ok(())
+}
+
+/// Asks `question` on the terminal; only `y` counts as yes.
+fn confirm(question: &str) -> Result<bool> {
+    let prompt = format!("{BOLD}> {question}{RESET} {YELLOW}[y/N]{RESET}: ");
+    if output::is_json() {
+        eprint!("{prompt}");
+    } else {
+        print!("{prompt}");
+        io::stdout().flush()?;
+    }
+
+    let mut buf = String::new();
+    io::stdin().read_line(&mut buf)?;
+    Ok(matches!(buf.trim(), "y" | "Y"))
+}
+
+fn identity(c: &select::Candidate) -> backup::Identity {
+    backup::Identity {
+        vid: c.id.vid,
+        pid: c.id.pid,
+        serial: c.serial.clone(),
+        path: Some(c.path.clone()),
+        manufacturer: c.manufacturer.clone(),
+        product: c.product.clone(),
+        model: model::lookup(c.id, c.manufacturer.as_deref(), c.product.as_deref())
+            .map(|m| m.name.to_owned()),
+    }
+}
+
+/// Saves the image read back from the device. When that isn't possible, or
+/// it doesn't decode cleanly (an echoing device hands back copies of one
+/// packet), the image is encoded from the saved state instead.
+fn backup_device(sel: &Selector, file: Option<&Path>) -> Result<()> {
+    let opened = HidApi::new()
+        .map_err(anyhow::Error::from)
+        .and_then(|api| select::open(&api, sel));
+    let readback = match &opened {
+        Ok((dev, _, _)) => read_image(dev),
+        Err(e) => Err(anyhow!("{e:#}")),
+    };
+
+    let (id, device) = match &opened {
+        Ok((_, found, key)) => (identity(found), key.clone()),
+        Err(_) => (
+            backup::Identity {
+                vid: sel.id.vid,
+                pid: sel.id.pid,
+                serial: sel.serial.clone(),
+                path: sel.path.clone(),
+                model: model::by_id(sel.id).map(|m| m.name.to_owned()),
+                ..backup::Identity::default()
+            },
+            state::device_key(sel.id.vid, sel.id.pid, sel.serial.as_deref()),
+        ),
+    };
 
-    if args.double_click_speed.is_some() {
+    let backup = match readback {
+        Ok(image) => backup::Backup::new(id, backup::Source::Device, &image),
+        Err(e) => {
+            eprintln!("{YELLOW}WARN:{RESET} Readback not possible: {e:#}");
+            let Some(saved) = state::load(&state::state_dir()?, &device)? else {
+                bail!("No saved state for {device} either.");
+            };
+            eprintln!(
+                "{YELLOW}WARN:{RESET} Backing up the saved state (last applied {})",
+                state::describe_age(saved.applied_at)
+            );
+            let model = match &opened {
+                Ok(_) => model_for(
+                    sel.id,
+                    id.manufacturer.as_deref(),
+                    id.product.as_deref(),
+                    &device,
+                )?,
+                Err(_) => model_by_id(sel.id, &device)?,
+            };
+            let image = ConfigImage::encode_on(model.base, &saved.config);
+            backup::Backup::new(id, backup::Source::Cache, &image)
+        }
+    };
+
+    let path = match file {
+        Some(file) => file.to_path_buf(),
+        None => backup::backup_dir()?.join(format!("{device}-{}.json", backup.created_at)),
+    };
+    backup::save(&path, &backup)?;
+    result!("> Backup written to {}", path.display());
+    output::emit(json!({
+        "command": "backup",
+        "ok": true,
+        "file": path,
+        "backup": backup,
+    }));
+    Ok(())
+}
+
+fn restore_backup(sel: &Selector, file: &Path, force: bool, args: &MouseArgs) -> Result<()> {
+    let (dry_run, no_confirm) = (args.dry_run, args.no_confirm);
+    let (backup, image) = backup::load(file)?;
+    let taken = &backup.device;
+    say!(
+        "\n{BOLD}{CYAN}Backup{RESET} {DIM}({}, {} from {}){RESET}",
+        file.display(),
+        state::describe_age(backup.created_at),
+        match backup.source {
+            backup::Source::Device => "read from the device",
+            backup::Source::Cache => "saved state",
+        }
+    );
+    let decoded = image::decode(image.packets())?;
+    print_config(&decoded.config, Level::Normal);
+    if !decoded.is_clean() {
+        say!("{YELLOW}[INFO]{RESET} The image has bytes outside the known settings, see `decode`");
+    }
+
+    let mut doc = json!({
+        "command": "restore",
+        "ok": true,
+        "applied": false,
+        "dry_run": dry_run,
+        "file": file,
+        "backup": backup,
+    });
+    if dry_run {
+        let device = state::device_key(sel.id.vid, sel.id.pid, sel.serial.as_deref());
+        let current = match state::load(&state::state_dir()?, &device) {
+            Ok(Some(saved)) => {
+                ConfigImage::encode_on(model_by_id(sel.id, &device)?.base, &saved.config)
+            }
+            _ => ConfigImage::default(),
+        };
+        print_plan(&current, &image);
+        doc["packets"] = plan_json(&current, &image);
+        output::emit(doc);
+        return Ok(());
+    }
+
+    let api = HidApi::new()?;
+    let (dev, found, device) = select::open(&api, sel)?;
+    let model = model_for(
+        found.id,
+        found.manufacturer.as_deref(),
+        found.product.as_deref(),
+        &device,
+    )?;
+    if found.id.vid != taken.vid || found.id.pid != taken.pid {
+        if !force {
+            bail!(Error::InvalidSetting(format!(
+                "backup was taken from {:04x}:{:04x}, not {}; use --force to restore anyway",
+                taken.vid, taken.pid, found.id
+            )));
+        }
         eprintln!(
-            "{RED}{BOLD}Error:{RESET} Changing 'double_click_speed' is not implemented. See notes on GitHub - https://github.com/vamsi200/Redgear-A15/tree/main#some-notes"
+            "{YELLOW}WARN:{RESET} Restoring a {:04x}:{:04x} backup to {}",
+            taken.vid, taken.pid, found.id
         );
-        std::process::exit(1);
     }
-
-    if args.rolling_speed.is_some() {
+    if taken.serial.is_some() && taken.serial != found.serial {
         eprintln!(
-            "{RED}{BOLD}Error:{RESET} Changing 'rolling_speed' is not implemented. See notes on GitHub - https://github.com/vamsi200/Redgear-A15/tree/main#some-notes"
+            "{YELLOW}WARN:{RESET} Backup is from serial {}, restoring to {}",
+            taken.serial.as_deref().unwrap_or("-"),
+            found.serial.as_deref().unwrap_or("a device without one")
         );
-        std::process::exit(1);
     }
+    doc["device"] = json!(device);
 
-    if !args.no_confirm {
-        if changes.is_empty() {
-            eprintln!("{RED}{BOLD}Error:{RESET} No changes detected. Nothing to apply.");
-            std::process::exit(1);
-        }
+    if !no_confirm && !confirm("Restore this image?")? {
+        result!("{RED}Aborted.{RESET}");
+        output::emit(doc);
+        return Ok(());
+    }
 
-        if let Some(_) = changes.iter().find(|(x, _)| x == "Continously") {
-            println!("{YELLOW}[INFO]{RESET} Enabling Continously makes repeat disabled!");
+    // Roll back to what was last applied, as the saved state has it.
+    let known_good = state::state_dir()
+        .and_then(|dir| state::load(&dir, &device))
+        .ok()
+        .flatten()
+        .map(|saved| ConfigImage::encode_on(model.base, &saved.config));
+
+    say!();
+    let mut results = Vec::new();
+    let outcome = write_verified(
+        &dev,
+        &image,
+        known_good.as_ref(),
+        &write_options(args, &device),
+        &mut results,
+    );
+    doc["results"] = json!(results);
+    doc["write"] = json!(outcome);
+    if let Some(e) = outcome.failure() {
+        let e = anyhow!(e);
+        doc["ok"] = json!(false);
+        add_error(&mut doc, &e);
+        output::emit(doc);
+        return Err(e);
+    }
+    report_write(&outcome);
+    doc["applied"] = json!(true);
+    doc["verified"] = json!(verified(&outcome));
+
+    // The saved state only holds settings, so it can only follow along when
+    // the image is fully understood.
+    if decoded.is_clean() {
+        match state::state_dir() {
+            Ok(dir) => record_applied(&dir, &device, &decoded.config, &invocation(), None),
+            Err(e) => eprintln!("{YELLOW}WARN:{RESET} Failed to save state: {e:#}"),
         }
+    } else {
+        eprintln!("{YELLOW}WARN:{RESET} Saved state not updated, the image has unknown bytes");
+    }
+    output::emit(doc);
+    Ok(())
+}
 
-        println!("\n{BOLD}{CYAN}Changes{RESET}");
-        println!("{DIM}──────────────────────────────────────────{RESET}");
+fn save_profile(sel: &Selector, name: &str, force: bool) -> Result<()> {
+    let current = read_current(sel)?;
+    let profile = profile::Profile::from_config(&current.config);
+    let path = profile::save_named(&profile::store_dir()?, name, &profile, force)?;
+    result!("> Saved profile `{name}` to {}", path.display());
+    result!("  {}", profile.summary());
+    output::emit(json!({
+        "command": "profile save",
+        "ok": true,
+        "name": name,
+        "path": path,
+        "profile": profile,
+    }));
+    Ok(())
+}
 
-        for (field, value) in &changes {
-            println!("{GREEN}+ {RESET}{BOLD}{}:{RESET} {}", field, value);
+fn list_profiles() -> Result<()> {
+    let dir = profile::store_dir()?;
+    let profiles = profile::list_named(&dir)?;
+    result!(
+        "\n{BOLD}{CYAN}Profiles{RESET} {DIM}({}){RESET}",
+        dir.display()
+    );
+    result!("{DIM}──────────────────────────────────────────{RESET}");
+    if profiles.is_empty() {
+        result!("  No saved profiles.");
+    }
+    let mut entries = Vec::new();
+    for (name, profile) in &profiles {
+        match profile {
+            Ok(p) => {
+                result!("  {BOLD}{name}{RESET}  {}", p.summary());
+                entries.push(json!({ "name": name, "profile": p }));
+            }
+            Err(e) => {
+                result!("  {BOLD}{name}{RESET}  {RED}{e:#}{RESET}");
+                entries.push(json!({ "name": name, "error": format!("{e:#}") }));
+            }
         }
+    }
+    result!("{DIM}──────────────────────────────────────────{RESET}");
+    output::emit(json!({
+        "command": "profile list",
+        "ok": true,
+        "profiles": entries,
+    }));
+    Ok(())
+}
 
-        println!("{DIM}──────────────────────────────────────────{RESET}");
+fn delete_profile(name: &str) -> Result<()> {
+    profile::delete_named(&profile::store_dir()?, name)?;
+    result!("> Deleted profile `{name}`");
+    output::emit(json!({
+        "command": "profile delete",
+        "ok": true,
+        "name": name,
+    }));
+    Ok(())
+}
 
-        print!("{BOLD}> Apply these changes?{RESET} {YELLOW}[y/N]{RESET}: ");
-        io::stdout().flush().unwrap();
+fn print_changes(changes: &[(String, String)]) {
+    say!("\n{BOLD}{CYAN}Changes{RESET}");
+    say!("{DIM}──────────────────────────────────────────{RESET}");
 
-        let mut buf = String::new();
-        io::stdin().read_line(&mut buf)?;
-        if !matches!(buf.trim(), "y" | "Y") {
-            println!("{RED}Aborted.{RESET}");
-            return Ok(());
+    for (field, value) in changes {
+        say!("{GREEN}+ {RESET}{BOLD}{}:{RESET} {}", field, value);
+    }
+
+    say!("{DIM}──────────────────────────────────────────{RESET}");
+}
+
+/// Every packet that would be sent, with the packets that differ from what's
+/// on the device now marked and annotated with the settings they carry.
+fn print_plan(current: &ConfigImage, planned: &ConfigImage) {
+    let changed = image::describe_changes(current, planned);
+    result!("\n{BOLD}{CYAN}Packet plan{RESET} {DIM}(dry run, nothing is sent){RESET}");
+    for (i, pkt) in planned.packets().iter().enumerate() {
+        match changed.iter().find(|(n, _)| *n == i) {
+            Some((_, names)) => result!(
+                "{GREEN}* {i:2}  {}{RESET}  {}",
+                bytes_to_hex(pkt),
+                names.join(", ")
+            ),
+            None => result!("{DIM}  {i:2}  {}{RESET}", bytes_to_hex(pkt)),
         }
     }
+    result!(
+        "{DIM}──────────────────────────────────────────{RESET}\n{} of {} packets change",
+        changed.len(),
+        image::PACKET_COUNT
+    );
+}
 
-    let repeat_hex = generate_hex_val_for_repeat!(repeat, COMMON_HEX);
+fn changes_json(changes: &[(String, String)]) -> Value {
+    changes
+        .iter()
+        .map(|(field, value)| json!({ "field": field, "value": value }))
+        .collect()
+}
 
-    let firing_interval_hex = generate_hex_for_interval!(firing_interval, repeat_hex.clone());
+fn plan_json(current: &ConfigImage, planned: &ConfigImage) -> Value {
+    let changed = image::describe_changes(current, planned);
+    planned
+        .packets()
+        .iter()
+        .enumerate()
+        .map(|(i, pkt)| {
+            let settings = changed
+                .iter()
+                .find(|(n, _)| *n == i)
+                .map(|(_, names)| names.clone());
+            json!({
+                "index": i,
+                "hex": bytes_to_hex(pkt),
+                "changed": settings.is_some(),
+                "settings": settings.unwrap_or_default(),
+            })
+        })
+        .collect()
+}
 
-    let led_brght_hex = if let Some(LedArgs {
-        led_brightness: Some(led_brght),
-        breathing_speed: None,
-    }) = args.led_args.clone()
-    {
-        gen_hex_for_led_brgt!(led_brght, firing_interval_hex.clone())
+fn print_config(cfg: &MouseConfig, level: Level) {
+    let led = &cfg.led_args;
+    let rows = [
+        ("DPI", format!("{:?}", cfg.dpi)),
+        ("LED Mode", format!("{:?}", cfg.led_mode)),
+        ("LED Status", format!("{:?}", cfg.led_status)),
+        (
+            "LED Brightness",
+            format!("{:?}", led.led_brightness.unwrap_or_default()),
+        ),
+        (
+            "LED Breathing Speed",
+            format!("{:?}", led.breathing_speed.unwrap_or_default()),
+        ),
+        ("Repeat", cfg.repeat.to_string()),
+        ("Firing Interval", cfg.firing_interval.to_string()),
+        ("Continously", format!("{:?}", cfg.continously)),
+    ];
+    for (field, value) in rows {
+        output::line(level, format_args!("  {BOLD}{field}:{RESET} {value}"));
+    }
+}
+
+fn decode_capture(file: &Path) -> Result<()> {
+    let text = if file == Path::new("-") {
+        io::read_to_string(io::stdin())?
     } else {
-        gen_hex_for_led_brgt!(led_brightness, firing_interval_hex.clone())
+        fs::read_to_string(file)?
     };
+    let decoded = image::decode(&image::parse_packets(&text)?)?;
+
+    result!("\n{BOLD}{CYAN}Decoded{RESET}");
+    result!("{DIM}──────────────────────────────────────────{RESET}");
+    print_config(&decoded.config, Level::Quiet);
+    result!("{DIM}──────────────────────────────────────────{RESET}");
+    result!(
+        "{GREEN}Recognized:{RESET} {}",
+        decoded.recognized.join(", ")
+    );
+
+    if decoded.is_clean() {
+        result!("{GREEN}No unknown bytes or mismatched pairs.{RESET}");
+    }
+    for m in &decoded.mismatched_pairs {
+        result!(
+            "{RED}Pair mismatch:{RESET} {} at packet {} byte {}: {:02x}{:02x} (expected {:02x}{:02x})",
+            m.field,
+            m.packet,
+            m.byte,
+            m.value,
+            m.complement,
+            m.value,
+            !m.value
+        );
+    }
+    for u in &decoded.unknown {
+        result!(
+            "{YELLOW}Unknown:{RESET} packet {} byte {}: {:02x} (base {:02x})",
+            u.packet,
+            u.byte,
+            u.value,
+            u.expected
+        );
+    }
+    output::emit(json!({
+        "command": "decode",
+        "ok": true,
+        "config": decoded.config,
+        "recognized": decoded.recognized,
+        "unknown": decoded.unknown,
+        "mismatched_pairs": decoded.mismatched_pairs,
+    }));
+    Ok(())
+}
 
-    let continously_hex = if let Some(FireControl {
-        continously: Some(cont),
-        ..
-    }) = args.fire_control.clone()
-    {
-        gen_hex_for_continously!(cont, led_brght_hex)
-    } else {
-        gen_hex_for_continously!(continously, led_brght_hex)
+fn main() {
+    let args = MouseArgs::parse();
+    let level = Level::from_flags(args.verbose, args.quiet);
+    let ran = output::init(args.output, level, args.trace_file.as_deref())
+        .context("creating the trace file")
+        .and_then(|()| run(&args));
+    if let Err(e) = ran {
+        eprintln!("{RED}{BOLD}Error:{RESET} {e:#}");
+        // Commands that got far enough have already printed their own
+        // document, with the error in it.
+        if !output::emitted() {
+            let mut doc = json!({ "ok": false });
+            add_error(&mut doc, &e);
+            output::emit(doc);
+        }
+        exit(error::exit_code(&e));
+    }
+}
+
+fn run(args: &MouseArgs) -> Result<()> {
+    if let Some(Commands::Decode { file }) = &args.command {
+        return decode_capture(file);
+    }
+
+    let sel = Selector::resolve(
+        args.device,
+        args.path.as_deref(),
+        args.serial.as_deref(),
+        args.interface,
+        &config::load(args.config.as_deref())?,
+    )?;
+    match &args.command {
+        Some(Commands::Status) => return show_status(&sel),
+        Some(Commands::List) => return list_devices(&sel),
+        Some(Commands::History) => return show_history(&sel),
+        Some(Commands::Backup { file }) => return backup_device(&sel, file.as_deref()),
+        Some(Commands::Restore { file, force }) => {
+            return restore_backup(&sel, file, *force, args);
+        }
+        Some(Commands::Export { file, format }) => {
+            return export_profile(&sel, file.as_deref(), *format);
+        }
+        Some(Commands::Calibrate { rounds }) => return calibrate_pacing(&sel, args, *rounds),
+        Some(Commands::Profile { action }) => match action {
+            ProfileCommand::Save { name, force } => return save_profile(&sel, name, *force),
+            ProfileCommand::List => return list_profiles(),
+            ProfileCommand::Delete { name } => return delete_profile(name),
+            // Applied like any other change below.
+            ProfileCommand::Load { .. } => {}
+        },
+        _ => {}
+    }
+
+    let mut patch = ConfigPatch::from_args(args)?;
+    match &args.command {
+        Some(Commands::Apply { file, .. }) => patch = patch.or(profile::load(file)?.patch()),
+        Some(Commands::Profile {
+            action: ProfileCommand::Load { name, .. },
+        }) => patch = patch.or(profile::load_named(&profile::store_dir()?, name)?.patch()),
+        _ => {}
+    }
+
+    if patch.moving_speed.is_some() {
+        bail!(Error::Unsupported(
+            "Changing 'moving_speed' is not implemented. See notes on GitHub - https://github.com/vamsi200/Redgear-A15/tree/main#some-notes.".into()
+        ));
+    }
+
+    if patch.double_click_speed.is_some() {
+        bail!(Error::Unsupported(
+            "Changing 'double_click_speed' is not implemented. See notes on GitHub - https://github.com/vamsi200/Redgear-A15/tree/main#some-notes".into()
+        ));
+    }
+
+    if patch.rolling_speed.is_some() {
+        bail!(Error::Unsupported(
+            "Changing 'rolling_speed' is not implemented. See notes on GitHub - https://github.com/vamsi200/Redgear-A15/tree/main#some-notes".into()
+        ));
+    }
+
+    let history_pick = match &args.command {
+        Some(Commands::Undo) => Some(history::Pick::Previous),
+        Some(Commands::Revert { n }) => Some(history::Pick::Number(*n)),
+        _ => None,
     };
+    if history_pick.is_some() && args.all_devices {
+        bail!(Error::InvalidSetting(
+            "undo and revert work on one device at a time, pick it with --serial or --path".into()
+        ));
+    }
+    if history_pick.is_none() && patch.changes().is_empty() {
+        if args.no_confirm {
+            bail!(Error::InvalidSetting("No Args Provided, use --help".into()));
+        }
+        bail!(Error::InvalidSetting(
+            "No changes detected. Nothing to apply.".into()
+        ));
+    }
 
-    let breathing_speed_hex = if let Some(LedArgs {
-        led_brightness: None,
-        breathing_speed: Some(brgt_speed),
-    }) = args.led_args.clone()
-    {
-        gen_hex_for_breathing_speed!(brgt_speed, continously_hex.clone())
+    let state_dir = state::state_dir();
+    let mut targets = Vec::new();
+    if args.dry_run {
+        // A dry run never touches HID, so it finds saved state only by the
+        // selected VID/PID and `--serial`, if given.
+        let key = state::device_key(sel.id.vid, sel.id.pid, sel.serial.as_deref());
+        let model = model_by_id(sel.id, &key)?;
+        if let Some(pick) = history_pick {
+            patch = patch.or(history_patch(&state_dir, &key, pick)?.0);
+        }
+        targets.push(Target::plan(
+            key.clone(),
+            key,
+            model,
+            None,
+            &state_dir,
+            &patch,
+        ));
     } else {
-        gen_hex_for_breathing_speed!(breathing_speed, continously_hex.clone())
+        let api = HidApi::new()?;
+        let found = if args.all_devices {
+            let candidates: Vec<_> = api
+                .device_list()
+                .map(select::Candidate::from_info)
+                .collect();
+            select::all_config_interfaces(&candidates, &sel, |c| select::feature_reports(&api, c))?
+                .into_iter()
+                .map(|c| {
+                    let key = select::device_key(c, &candidates);
+                    let label = select::unit_name(c, &candidates);
+                    (select::open_candidate(&api, c), c.clone(), key, label)
+                })
+                .collect()
+        } else {
+            let (dev, found, key) = select::open(&api, &sel)?;
+            let label = found.serial.clone();
+            vec![(Ok(dev), found, key, label)]
+        };
+        for (dev, c, key, label) in found {
+            let label = label.unwrap_or_else(|| c.path.clone());
+            let model = model_for(
+                c.id,
+                c.manufacturer.as_deref(),
+                c.product.as_deref(),
+                &label,
+            )?;
+            let mut undo_to = None;
+            if let Some(pick) = history_pick {
+                let (picked, n) = history_patch(&state_dir, &key, pick)?;
+                patch = patch.or(picked);
+                undo_to = n;
+            }
+            targets.push(Target {
+                undo_to,
+                ..Target::plan(key, label, model, Some(dev), &state_dir, &patch)
+            });
+        }
+    }
+
+    let changes = patch.changes();
+    let source = invocation();
+
+    let doc = |targets: &[Target]| {
+        let mut doc = json!({
+            "command": "apply",
+            "ok": targets.iter().all(|t| t.error.is_none()),
+            "dry_run": args.dry_run,
+            "changes": changes_json(&changes),
+        });
+        if args.all_devices {
+            doc["devices"] = targets.iter().map(Target::json).collect();
+        } else if let (Some(doc), Some(Value::Object(fields))) =
+            (doc.as_object_mut(), targets.first().map(Target::json))
+        {
+            doc.extend(fields);
+        }
+        doc
     };
 
-    let final_hex = if let Some(commands) = args.command.clone() {
-        match commands {
-            Commands::Dpi { dpi_val, .. } => {
-                gen_hex_for_dpi!(dpi_val, breathing_speed_hex)
-            }
-            Commands::Led { .. } => {
-                let led_mode = if let Some(Commands::Led { mode, .. }) = args.command {
-                    mode
-                } else {
-                    default_val.led_mode
-                };
-                gen_hex_for_led!(led_mode, breathing_speed_hex.clone())
-            }
-            Commands::LedStatus { state, .. } => {
-                gen_hex_for_led!(state, breathing_speed_hex.clone())
-            }
-            Commands::Reset => {
-                let mut reset_hex = Vec::new();
-                for val in reset_val() {
-                    match val {
-                        Reset::RepeatVal(repeat) => {
-                            reset_hex = generate_hex_val_for_repeat!(repeat, COMMON_HEX)
-                        }
-                        Reset::FiringInterval(firing_interval) => {
-                            reset_hex = generate_hex_for_interval!(firing_interval, reset_hex)
-                        }
-                        Reset::Continously(cstate) => {
-                            reset_hex = gen_hex_for_continously!(cstate, reset_hex)
-                        }
-                        Reset::DpiVal(dpival) => reset_hex = gen_hex_for_dpi!(dpival, reset_hex),
-                        Reset::LedStatus(lstatus) => {
-                            reset_hex = gen_hex_for_led!(lstatus, reset_hex)
-                        }
-                        Reset::LedBrightness(led_brightness) => {
-                            reset_hex = gen_hex_for_led_brgt!(led_brightness, reset_hex)
-                        }
-                        Reset::LedMode(led_mode) => {
-                            reset_hex = gen_hex_for_led!(led_mode, reset_hex)
-                        }
-                        Reset::BreathingSpeed(breathing_speed) => {
-                            reset_hex = gen_hex_for_breathing_speed!(breathing_speed, reset_hex)
-                        }
-                    }
-                }
-                reset_hex
-            }
+    for t in &targets {
+        let missing = t.model.unsupported(&patch);
+        if !missing.is_empty() {
+            bail!(Error::Unsupported(format!(
+                "{} ({}) doesn't support: {}",
+                t.model.name,
+                t.label,
+                missing.join(", ")
+            )));
+        }
+    }
+
+    if args.dry_run {
+        print_changes(&changes);
+        for t in &targets {
+            print_plan(&t.current, &t.planned);
+        }
+        output::emit(doc(&targets));
+        return Ok(());
+    }
+
+    if !args.no_confirm {
+        if changes.iter().any(|(x, _)| x == "Continously") {
+            say!("{YELLOW}[INFO]{RESET} Enabling Continously makes repeat disabled!");
+        }
+
+        print_changes(&changes);
+        if args.all_devices {
+            let labels: Vec<_> = targets.iter().map(|t| t.label.as_str()).collect();
+            say!("{BOLD}Devices:{RESET} {}", labels.join(", "));
+        }
+
+        if !confirm("Apply these changes?")? {
+            result!("{RED}Aborted.{RESET}");
+            output::emit(doc(&targets));
+            return Ok(());
+        }
+    }
+
+    for t in &mut targets {
+        say!();
+        if args.all_devices {
+            say!("{BOLD}{CYAN}{}{RESET}", t.label);
         }
-    } else if let Some(fire_control_commands) = args.fire_control {
-        match fire_control_commands {
-            FireControl {
-                repeat: Some(repeat),
-                firing_interval: None,
-                ..
-            } => {
-                generate_hex_val_for_repeat!(repeat, COMMON_HEX)
+        let opts = write_options(args, &t.device);
+        t.apply(&state_dir, &source, &opts);
+    }
+
+    if args.all_devices {
+        result!("\n{BOLD}{CYAN}Summary{RESET}");
+        for t in &targets {
+            match &t.error {
+                None => result!("  {GREEN}ok{RESET}      {}", t.label),
+                Some(e) => result!("  {RED}failed{RESET}  {}: {e:#}", t.label),
             }
+        }
+    }
+
+    output::emit(doc(&targets));
+    let total = targets.len();
+    let mut failed = targets.into_iter().filter_map(|t| t.error);
+    match failed.next() {
+        None => Ok(()),
+        Some(e) if !args.all_devices => Err(e),
+        // The first failure decides the exit code.
+        Some(e) => Err(e.context(format!("{} of {total} devices failed", failed.count() + 1))),
+    }
+}
+
+/// `error`, `kind` and `exit_code` fields describing `e`.
+fn add_error(doc: &mut Value, e: &anyhow::Error) {
+    doc["error"] = json!(format!("{e:#}"));
+    doc["kind"] = json!(error::kind(e));
+    doc["exit_code"] = json!(error::exit_code(e));
+}
+
+/// How to write to `device`: flags and environment variables first, then
+/// what `calibrate` recorded for it, then the defaults.
+fn write_options(args: &MouseArgs, device: &str) -> WriteOptions {
+    let calibrated = state::state_dir().and_then(|dir| pacing::load(&dir, device));
+    let mut pacing = match calibrated {
+        Ok(Some(calibrated)) => calibrated.pacing,
+        Ok(None) => Pacing::default(),
+        Err(e) => {
+            eprintln!("{YELLOW}WARN:{RESET} Ignoring calibrated pacing: {e:#}");
+            Pacing::default()
+        }
+    };
+    if let Some(ms) = args.packet_delay {
+        pacing.min_delay_ms = ms;
+    }
+    if let Some(ms) = args.ack_timeout {
+        pacing.timeout_ms = ms;
+    }
+    if let Some(n) = args.packet_retries {
+        pacing.retries = n;
+    }
+    WriteOptions {
+        pacing,
+        retries: args.retries,
+    }
+}
+
+fn calibrate_pacing(sel: &Selector, args: &MouseArgs, rounds: usize) -> Result<()> {
+    if args.dry_run {
+        bail!(Error::InvalidSetting(
+            "calibrate has to write to the device, it can't be a dry run".into()
+        ));
+    }
+    if args.all_devices {
+        bail!(Error::InvalidSetting(
+            "calibrate works on one device at a time, pick it with --serial or --path".into()
+        ));
+    }
+    let api = HidApi::new()?;
+    let (dev, found, device) = select::open(&api, sel)?;
+    let model = model_for(
+        found.id,
+        found.manufacturer.as_deref(),
+        found.product.as_deref(),
+        &device,
+    )?;
+    let dir = state::state_dir()?;
+
+    // Calibrate by rewriting what's already there, so no setting changes.
+    // A readback that doesn't decode cleanly may be an echo of one page, and
+    // writing that back dozens of times would wreck the configuration.
+    let image = match read_image(&dev) {
+        Ok(image) => image,
+        Err(e) => {
+            eprintln!("{YELLOW}WARN:{RESET} Readback not possible: {e:#}");
+            let Some(saved) = state::load(&dir, &device)? else {
+                bail!("No saved state for {device} either; apply a configuration first");
+            };
+            ConfigImage::encode_on(model.base, &saved.config)
+        }
+    };
+
+    say!(
+        "Calibration rewrites the current configuration up to {} times.",
+        rounds.max(1) * pacing::CANDIDATES_MS.len()
+    );
+    let mut doc = json!({
+        "command": "calibrate",
+        "ok": true,
+        "device": device,
+    });
+    if !args.no_confirm && !confirm("Start calibrating?")? {
+        result!("{RED}Aborted.{RESET}");
+        output::emit(doc);
+        return Ok(());
+    }
 
-            FireControl {
-                repeat: None,
-                firing_interval: Some(interval),
-                ..
-            } => {
-                generate_hex_for_interval!(interval, COMMON_HEX)
+    let base = write_options(args, &device).pacing;
+    let (steps, calibrated) =
+        pacing::calibrate(&dev, &device, &image, pacing::CANDIDATES_MS, base, rounds)?;
+    say!("\n{BOLD}{CYAN}Calibration{RESET}");
+    for step in &steps {
+        let verdict = match &step.error {
+            None => format!("{GREEN}ok{RESET}"),
+            Some(e) => format!("{RED}failed{RESET}  {e}"),
+        };
+        say!(
+            "  {:>3} ms  slowest ack {:>6.1} ms  {verdict}",
+            step.min_delay_ms,
+            step.max_latency_us as f64 / 1000.0
+        );
+    }
+    doc["steps"] = json!(steps);
+    let Some(calibrated) = calibrated else {
+        bail!(Error::SendFailed(format!(
+            "the mouse wasn't reliable even at {} ms, nothing recorded",
+            pacing::CANDIDATES_MS[0]
+        )));
+    };
+    let path = pacing::save(&dir, &calibrated)?;
+    result!(
+        "> Recorded {} ms for {device} in {}",
+        calibrated.pacing.min_delay_ms,
+        path.display()
+    );
+    doc["pacing"] = json!(calibrated.pacing);
+    doc["file"] = json!(path);
+    output::emit(doc);
+    Ok(())
+}
+
+/// One line on how a successful write was checked.
+fn report_write(outcome: &WriteOutcome) {
+    match &outcome.verification {
+        Some(Verification::Match) => result!("{GREEN}> Written and verified.{RESET}"),
+        Some(Verification::Unavailable { reason }) => result!(
+            "{YELLOW}> Written, NOT verified:{RESET} the configuration couldn't be read back \
+             ({reason}), so nothing checked that the mouse stored it."
+        ),
+        _ => result!("{YELLOW}> Written, NOT verified.{RESET}"),
+    }
+}
+
+/// Whether `outcome` read the configuration back and found it as written.
+fn verified(outcome: &WriteOutcome) -> bool {
+    outcome.verification == Some(Verification::Match)
+}
+
+/// The registry entry for a device. An unknown one is refused: its layout
+/// and what it supports can't be assumed from the VID/PID alone.
+fn model_for(
+    id: DeviceId,
+    manufacturer: Option<&str>,
+    product: Option<&str>,
+    label: &str,
+) -> Result<&'static Model> {
+    model::lookup(id, manufacturer, product).ok_or_else(|| {
+        Error::Unsupported(format!(
+            "{id} ({label}, \"{}\" / \"{}\") is not a known model; add it to src/model.rs",
+            manufacturer.unwrap_or("-"),
+            product.unwrap_or("-"),
+        ))
+        .into()
+    })
+}
+
+/// `model_for` without descriptor strings, for a device that isn't opened.
+fn model_by_id(id: DeviceId, label: &str) -> Result<&'static Model> {
+    model::by_id(id).ok_or_else(|| {
+        Error::Unsupported(format!(
+            "{id} ({label}) is not a known model; add it to src/model.rs"
+        ))
+        .into()
+    })
+}
+
+/// The command line as typed, minus the program name, for the history.
+fn invocation() -> String {
+    std::env::args().skip(1).collect::<Vec<_>>().join(" ")
+}
+
+/// Saves `config` as the device's last applied state and appends it to its
+/// history. Failures only warn; the device has been written either way.
+fn record_applied(
+    dir: &Path,
+    device: &str,
+    config: &MouseConfig,
+    source: &str,
+    undo_to: Option<usize>,
+) {
+    let applied_at = state::now();
+    let saved = State {
+        device: device.to_owned(),
+        applied_at,
+        config: config.clone(),
+    };
+    if let Err(e) = state::save(dir, &saved) {
+        eprintln!("{YELLOW}WARN:{RESET} Failed to save state: {e:#}");
+    }
+    let entry = history::Entry {
+        applied_at,
+        source: source.to_owned(),
+        config: config.clone(),
+        undo_to,
+    };
+    if let Err(e) = history::append(dir, device, &entry) {
+        eprintln!("{YELLOW}WARN:{RESET} Failed to record history: {e:#}");
+    }
+}
+
+/// Every setting of the history entry `pick` as a patch, with the entry's
+/// number when it's an undo (see `history::Entry::undo_to`).
+fn history_patch(
+    state_dir: &Result<PathBuf>,
+    device: &str,
+    pick: history::Pick,
+) -> Result<(ConfigPatch, Option<usize>)> {
+    let dir = state_dir.as_ref().map_err(|e| anyhow!("{e:#}"))?;
+    let entries = history::load(dir, device)?;
+    let (n, entry) = history::pick(&entries, pick)?;
+    say!(
+        "> Going back to the config applied {} by `{}`",
+        state::describe_age(entry.applied_at),
+        entry.source
+    );
+    let undo_to = (pick == history::Pick::Previous).then_some(n);
+    Ok((
+        profile::Profile::from_config(&entry.config).patch(),
+        undo_to,
+    ))
+}
+
+fn show_history(sel: &Selector) -> Result<()> {
+    let device = match HidApi::new()
+        .map_err(anyhow::Error::from)
+        .and_then(|api| select::open(&api, sel))
+    {
+        Ok((_, _, key)) => key,
+        Err(_) => state::device_key(sel.id.vid, sel.id.pid, sel.serial.as_deref()),
+    };
+    let entries = history::load(&state::state_dir()?, &device)?;
+
+    result!("\n{BOLD}{CYAN}History{RESET} {DIM}({device}){RESET}");
+    result!("{DIM}──────────────────────────────────────────{RESET}");
+    if entries.is_empty() {
+        result!("  Nothing applied yet.");
+    }
+    for (i, entry) in entries.iter().enumerate() {
+        result!(
+            "  {BOLD}{:>3}{RESET}  {DIM}{:>8}{RESET}  {}{}",
+            i + 1,
+            state::describe_age(entry.applied_at),
+            entry.source,
+            if i + 1 == entries.len() {
+                "  (current)"
+            } else {
+                ""
             }
+        );
+        result!(
+            "       {}",
+            profile::Profile::from_config(&entry.config).summary()
+        );
+    }
+    result!("{DIM}──────────────────────────────────────────{RESET}");
+    output::emit(json!({
+        "command": "history",
+        "ok": true,
+        "device": device,
+        "entries": entries,
+    }));
+    Ok(())
+}
 
-            FireControl {
-                repeat: Some(repeat),
-                firing_interval: Some(interval),
-                ..
-            } => {
-                let repeat_hex = generate_hex_val_for_repeat!(repeat, COMMON_HEX);
-                generate_hex_for_interval!(interval, repeat_hex)
+/// One device a patch is applied to, with its plan and outcome.
+struct Target {
+    /// State key, see `state::device_key`.
+    device: String,
+    /// Serial, or `select::unit_name` with `--all-devices`; the path without
+    /// either.
+    label: String,
+    model: &'static Model,
+    /// `None` on a dry run.
+    dev: Option<Result<hidapi::HidDevice>>,
+    config: MouseConfig,
+    current: ConfigImage,
+    planned: ConfigImage,
+    /// The image last applied successfully, if there is saved state.
+    known_good: Option<ConfigImage>,
+    results: Vec<device::PacketResult>,
+    write: Option<WriteOutcome>,
+    applied: bool,
+    /// Set when this write is an `undo`, see `history::Entry::undo_to`.
+    undo_to: Option<usize>,
+    error: Option<anyhow::Error>,
+}
+
+impl Target {
+    /// Layers `patch` over the state last applied to `device`. Settings not
+    /// mentioned on the command line keep whatever was applied last, instead
+    /// of falling back to the defaults.
+    fn plan(
+        device: String,
+        label: String,
+        model: &'static Model,
+        dev: Option<Result<hidapi::HidDevice>>,
+        state_dir: &Result<PathBuf>,
+        patch: &ConfigPatch,
+    ) -> Self {
+        let saved = match state_dir {
+            Ok(dir) => state::load(dir, &device),
+            Err(e) => Err(anyhow!("{e:#}")),
+        };
+        let saved = match saved {
+            Ok(saved) => saved.map(|s| s.config),
+            Err(e) => {
+                eprintln!("{YELLOW}WARN:{RESET} Ignoring saved state: {e:#}");
+                None
             }
+        };
+        let base = saved.clone().unwrap_or_default();
+        let config = patch.apply(&base);
+        Self {
+            device,
+            label,
+            model,
+            dev,
+            current: ConfigImage::encode_on(model.base, &base),
+            planned: ConfigImage::encode_on(model.base, &config),
+            known_good: saved.map(|cfg| ConfigImage::encode_on(model.base, &cfg)),
+            config,
+            results: Vec::new(),
+            write: None,
+            applied: false,
+            undo_to: None,
+            error: None,
+        }
+    }
 
-            FireControl {
-                continously: Some(continously),
-                ..
-            } => {
-                gen_hex_for_continously!(continously, repeat_hex)
+    fn apply(&mut self, state_dir: &Result<PathBuf>, source: &str, opts: &WriteOptions) {
+        let dev = match self.dev.take() {
+            Some(Ok(dev)) => dev,
+            Some(Err(e)) => {
+                self.error = Some(e);
+                return;
             }
+            None => return,
+        };
+        // See `image::COMMON_HEX` for why unchanged packets are sent too.
+        let changed = image::describe_changes(&self.current, &self.planned).len();
+        say!(
+            "> {changed} of {} packets change, writing the full image",
+            image::PACKET_COUNT
+        );
+        let outcome = write_verified(
+            &dev,
+            &self.planned,
+            self.known_good.as_ref(),
+            opts,
+            &mut self.results,
+        );
+        self.error = outcome.failure().map(anyhow::Error::from);
+        if self.error.is_none() {
+            report_write(&outcome);
+        }
+        self.write = Some(outcome);
+        if self.error.is_some() {
+            return;
+        }
+        self.applied = true;
 
-            _ => Vec::new(),
+        if let Ok(dir) = state_dir {
+            record_applied(dir, &self.device, &self.config, source, self.undo_to);
         }
-    } else if let Some(led_args) = args.led_args {
-        match led_args {
-            LedArgs {
-                breathing_speed: Some(breathing_speed),
-                ..
-            } => {
-                gen_hex_for_breathing_speed!(breathing_speed, COMMON_HEX)
-            }
-            _ => Vec::new(),
-        }
-    } else if let Some(..) = args.moving_speed {
-        todo!()
-    } else if let Some(..) = args.double_click_speed {
-        todo!()
-    } else if let Some(..) = args.rolling_speed {
-        todo!()
-    } else {
-        eprintln!("Error: No Args Provided, use --help");
-        exit(1);
-    };
+    }
 
-    let packets: Vec<Vec<u8>> = final_hex
-        .iter()
-        .map(|val| convert_str_hex(val.as_str()))
-        .collect();
+    fn json(&self) -> Value {
+        let mut doc = json!({
+            "device": self.device,
+            "label": self.label,
+            "model": self.model.name,
+            "ok": self.error.is_none(),
+            "applied": self.applied,
+            "config": self.config,
+            "packets": plan_json(&self.current, &self.planned),
+        });
+        if !self.results.is_empty() {
+            doc["results"] = json!(self.results);
+        }
+        if let Some(write) = &self.write {
+            doc["write"] = json!(write);
+            doc["verified"] = json!(verified(write));
+        }
+        if let Some(e) = &self.error {
+            add_error(&mut doc, e);
+        }
+        doc
+    }
+}
 
-    let api = HidApi::new()?;
-    let dev = api.open(VID, PID)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    println!();
-    if let Ok(_) = send_report_to_mouse(packets, dev) {
-        println!("> All reports processed.");
+    fn run_with(argv: &[&str]) -> Result<()> {
+        run(
+            &MouseArgs::try_parse_from(std::iter::once("redgear-a15").chain(argv.iter().copied()))
+                .unwrap(),
+        )
     }
 
-    Ok(())
+    #[test]
+    fn conflicting_options_are_invalid_settings() {
+        for argv in [
+            &["--all-devices", "undo"][..],
+            &["--dry-run", "calibrate"],
+            &["--all-devices", "calibrate"],
+        ] {
+            let err = run_with(argv).unwrap_err();
+            assert_eq!(error::exit_code(&err), 8, "{argv:?}: {err:#}");
+        }
+    }
+
+    #[test]
+    fn unknown_models_are_unsupported() {
+        let id = DeviceId::default();
+        assert!(model_for(id, None, Some("USB Optical Mouse"), "x").is_ok());
+        for product in [Some("Gaming Mouse"), None] {
+            let err = model_for(id, None, product, "x").unwrap_err();
+            assert_eq!(error::exit_code(&err), 9, "{product:?}: {err:#}");
+        }
+        let other = DeviceId {
+            vid: 0x1bcf,
+            pid: 1,
+        };
+        assert_eq!(error::exit_code(&model_by_id(other, "x").unwrap_err()), 9);
+    }
 }
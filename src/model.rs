@@ -0,0 +1,185 @@
+use crate::image::{self, PACKET_COUNT, Packet};
+use crate::patch::ConfigPatch;
+use crate::select::DeviceId;
+use crate::{DpiVal, LedBrightness, LedMode};
+
+/// Which fire-control settings a model exposes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FireControlFeatures {
+    pub repeat: bool,
+    pub firing_interval: bool,
+    pub continuous: bool,
+}
+
+/// A mouse known to speak this protocol, and what it can do. Several OEMs
+/// ship under the same Sunplus VID/PID, so `manufacturer` and `product`
+/// (matched against the USB descriptor strings) tell them apart; `None`
+/// matches anything.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Model {
+    pub name: &'static str,
+    pub id: DeviceId,
+    pub manufacturer: Option<&'static str>,
+    pub product: Option<&'static str>,
+    pub dpi: &'static [DpiVal],
+    pub led_modes: &'static [LedMode],
+    pub brightness: &'static [LedBrightness],
+    pub breathing_speed: bool,
+    pub fire_control: FireControlFeatures,
+    /// Image the settings are encoded on top of.
+    pub base: &'static [Packet; PACKET_COUNT],
+}
+
+pub const REDGEAR_A15: Model = Model {
+    name: "Redgear A-15",
+    id: DeviceId {
+        vid: 0x1bcf,
+        pid: 0x08a0,
+    },
+    // Only the product string is set; `lsusb` shows the A-15 as
+    // "USB Optical Mouse" with no manufacturer.
+    manufacturer: None,
+    product: Some("USB Optical Mouse"),
+    dpi: &DpiVal::ALL,
+    led_modes: &[
+        LedMode::Dpi,
+        LedMode::Multi,
+        LedMode::Rainbow,
+        LedMode::FloeLight,
+        LedMode::Waltz,
+        LedMode::FourSeasons,
+        LedMode::Off,
+    ],
+    brightness: &LedBrightness::ALL,
+    breathing_speed: true,
+    fire_control: FireControlFeatures {
+        repeat: true,
+        firing_interval: true,
+        continuous: true,
+    },
+    base: &image::BASE_IMAGE,
+};
+
+/// Every known model. Add rebadged variants here, with descriptor strings
+/// when they share a VID/PID with an existing entry.
+pub const MODELS: &[Model] = &[REDGEAR_A15];
+
+impl Model {
+    fn matches(&self, id: DeviceId, manufacturer: Option<&str>, product: Option<&str>) -> bool {
+        let string_matches = |want: Option<&str>, got: Option<&str>| match want {
+            Some(want) => got.is_some_and(|got| got.trim().eq_ignore_ascii_case(want)),
+            None => true,
+        };
+        self.id == id
+            && string_matches(self.manufacturer, manufacturer)
+            && string_matches(self.product, product)
+    }
+
+    /// Settings in `patch` this model doesn't have, as `setting value` strings.
+    pub fn unsupported(&self, patch: &ConfigPatch) -> Vec<String> {
+        let mut missing = Vec::new();
+        if let Some(dpi) = patch.dpi.filter(|d| !self.dpi.contains(d)) {
+            missing.push(format!("DPI {dpi:?}"));
+        }
+        if let Some(mode) = patch.led_mode.filter(|m| !self.led_modes.contains(m)) {
+            missing.push(format!("LED mode {mode:?}"));
+        }
+        if let Some(br) = patch
+            .led_brightness
+            .filter(|b| !self.brightness.contains(b))
+        {
+            missing.push(format!("LED brightness {br:?}"));
+        }
+        if patch.breathing_speed.is_some() && !self.breathing_speed {
+            missing.push("breathing speed".into());
+        }
+        let fire = self.fire_control;
+        if patch.repeat.is_some() && !fire.repeat {
+            missing.push("repeat".into());
+        }
+        if patch.firing_interval.is_some() && !fire.firing_interval {
+            missing.push("firing interval".into());
+        }
+        if patch.continously.is_some() && !fire.continuous {
+            missing.push("continuous firing".into());
+        }
+        missing
+    }
+}
+
+/// The model matching `id` and the descriptor strings, preferring entries
+/// that name more strings over catch-all ones.
+pub fn lookup(
+    id: DeviceId,
+    manufacturer: Option<&str>,
+    product: Option<&str>,
+) -> Option<&'static Model> {
+    MODELS
+        .iter()
+        .filter(|m| m.matches(id, manufacturer, product))
+        .max_by_key(|m| usize::from(m.manufacturer.is_some()) + usize::from(m.product.is_some()))
+}
+
+/// The first model with `id`, for when there are no descriptor strings to
+/// go by because the device wasn't opened (a dry run, or it's unplugged).
+pub fn by_id(id: DeviceId) -> Option<&'static Model> {
+    MODELS.iter().find(|m| m.id == id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a15_is_found_by_its_product_string() {
+        let model = lookup(REDGEAR_A15.id, None, Some("USB Optical Mouse")).unwrap();
+        assert_eq!(model.name, "Redgear A-15");
+        assert!(lookup(REDGEAR_A15.id, None, Some("Gaming Mouse")).is_none());
+        assert!(lookup(REDGEAR_A15.id, None, None).is_none());
+        let other = DeviceId {
+            vid: 0x1bcf,
+            pid: 1,
+        };
+        assert!(lookup(other, None, Some("USB Optical Mouse")).is_none());
+        assert_eq!(by_id(REDGEAR_A15.id), Some(&REDGEAR_A15));
+        assert!(by_id(other).is_none());
+    }
+
+    #[test]
+    fn descriptor_strings_pick_the_more_specific_model() {
+        let rebadge = Model {
+            name: "Rebadge",
+            product: Some("Other Mouse"),
+            ..REDGEAR_A15
+        };
+        assert!(rebadge.matches(REDGEAR_A15.id, None, Some("other mouse")));
+        assert!(!rebadge.matches(REDGEAR_A15.id, None, Some("USB Optical Mouse")));
+        assert!(!rebadge.matches(REDGEAR_A15.id, None, None));
+    }
+
+    #[test]
+    fn unsupported_settings_are_reported() {
+        let limited = Model {
+            dpi: &[DpiVal::DPI1, DpiVal::DPI2],
+            led_modes: &[LedMode::Off],
+            fire_control: FireControlFeatures {
+                repeat: true,
+                firing_interval: true,
+                continuous: false,
+            },
+            ..REDGEAR_A15
+        };
+        let patch = ConfigPatch {
+            dpi: Some(DpiVal::DPI3),
+            led_mode: Some(LedMode::Off),
+            continously: Some(crate::ContinouslyState::Enable),
+            repeat: Some(4),
+            ..ConfigPatch::default()
+        };
+        assert_eq!(
+            limited.unsupported(&patch),
+            ["DPI DPI3", "continuous firing"]
+        );
+        assert!(REDGEAR_A15.unsupported(&patch).is_empty());
+    }
+}
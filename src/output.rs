@@ -0,0 +1,163 @@
+use clap::ValueEnum;
+use serde_json::Value;
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    sync::{
+        Mutex, OnceLock,
+        atomic::{AtomicBool, AtomicU8, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Human,
+    Json,
+}
+
+/// How much gets printed, from `-q` to `-vv`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Only the final result (and warnings and errors).
+    Quiet,
+    Normal,
+    /// Adds every report sent and received.
+    Verbose,
+    /// Adds timestamps, and the time since the packet was sent.
+    Debug,
+}
+
+impl Level {
+    pub fn from_flags(verbose: u8, quiet: bool) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Level::Quiet,
+            (false, 0) => Level::Normal,
+            (false, 1) => Level::Verbose,
+            (false, _) => Level::Debug,
+        }
+    }
+}
+
+static JSON: AtomicBool = AtomicBool::new(false);
+static EMITTED: AtomicBool = AtomicBool::new(false);
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Normal as u8);
+static START: OnceLock<Instant> = OnceLock::new();
+static TRACE: OnceLock<Mutex<File>> = OnceLock::new();
+
+/// Sets up output for the run. With `trace`, every report sent and received
+/// is also written there with a timestamp, whatever the level.
+pub fn init(format: OutputFormat, level: Level, trace: Option<&Path>) -> io::Result<()> {
+    JSON.store(format == OutputFormat::Json, Ordering::Relaxed);
+    LEVEL.store(level as u8, Ordering::Relaxed);
+    START.get_or_init(Instant::now);
+    if let Some(path) = trace {
+        let mut file = File::create(path)?;
+        writeln!(
+            file,
+            "# redgear-a15 {} trace, started at {} (seconds since the Unix epoch)",
+            env!("CARGO_PKG_VERSION"),
+            crate::state::now()
+        )?;
+        let _ = TRACE.set(Mutex::new(file));
+    }
+    Ok(())
+}
+
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Whether messages at `level` are printed.
+pub fn shows(level: Level) -> bool {
+    LEVEL.load(Ordering::Relaxed) >= level as u8
+}
+
+/// Prints `args` if the level allows. Goes to stdout normally, and to stderr
+/// under `--output json` so stdout only ever carries the JSON document.
+pub fn line(level: Level, args: fmt::Arguments) {
+    if !shows(level) {
+        return;
+    }
+    if is_json() {
+        eprintln!("{args}");
+    } else {
+        println!("{args}");
+    }
+}
+
+/// Progress and explanations, left out with `-q`.
+macro_rules! say {
+    () => {
+        $crate::output::line($crate::output::Level::Normal, format_args!(""))
+    };
+    ($($arg:tt)*) => {
+        $crate::output::line($crate::output::Level::Normal, format_args!($($arg)*))
+    };
+}
+pub(crate) use say;
+
+/// The outcome of a command, printed even with `-q`.
+macro_rules! result {
+    ($($arg:tt)*) => {
+        $crate::output::line($crate::output::Level::Quiet, format_args!($($arg)*))
+    };
+}
+pub(crate) use result;
+
+/// Details only worth seeing with `-v`.
+macro_rules! detail {
+    ($($arg:tt)*) => {
+        $crate::output::line($crate::output::Level::Verbose, format_args!($($arg)*))
+    };
+}
+pub(crate) use detail;
+
+/// One report sent or received, e.g. `> SET_REPORT 0401...`. `since_send` is
+/// the time since the packet it answers went out.
+pub fn packet(args: fmt::Arguments, since_send: Option<Duration>) {
+    let at = START.get_or_init(Instant::now).elapsed().as_secs_f64();
+    let latency = since_send
+        .map(|d| format!("  (+{:.3} ms)", d.as_secs_f64() * 1000.0))
+        .unwrap_or_default();
+    if let Some(trace) = TRACE.get()
+        && let Ok(mut file) = trace.lock()
+    {
+        let _ = writeln!(file, "[{at:12.6}] {args}{latency}");
+    }
+    if shows(Level::Debug) {
+        line(Level::Debug, format_args!("[{at:12.6}] {args}{latency}"));
+    } else {
+        line(Level::Verbose, args);
+    }
+}
+
+/// Prints the result document for `--output json`; a no-op otherwise.
+pub fn emit(doc: Value) {
+    EMITTED.store(true, Ordering::Relaxed);
+    if is_json() {
+        println!("{}", serde_json::to_string_pretty(&doc).unwrap_or_default());
+    }
+}
+
+/// Whether `emit` has been called.
+pub fn emitted() -> bool {
+    EMITTED.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_pick_the_level() {
+        assert_eq!(Level::from_flags(0, false), Level::Normal);
+        assert_eq!(Level::from_flags(1, false), Level::Verbose);
+        assert_eq!(Level::from_flags(3, false), Level::Debug);
+        assert_eq!(Level::from_flags(0, true), Level::Quiet);
+        assert!(Level::Debug > Level::Verbose && Level::Quiet < Level::Normal);
+    }
+}
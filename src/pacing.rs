@@ -0,0 +1,234 @@
+use crate::device;
+use crate::error::Error;
+use crate::image::ConfigImage;
+use crate::output::say;
+use crate::transport::Transport;
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// How packets are paced. After each SET_REPORT the device gets `min_delay_ms`,
+/// then GET_REPORT is polled until it hands the packet back, for up to
+/// `timeout_ms`. A begin or mode-select packet that isn't handed back is
+/// resent up to `retries` times; later ones end the session instead (see
+/// `session::Session`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pacing {
+    pub min_delay_ms: u64,
+    pub timeout_ms: u64,
+    pub retries: usize,
+}
+
+impl Default for Pacing {
+    fn default() -> Self {
+        Self {
+            min_delay_ms: 5,
+            timeout_ms: 300,
+            retries: 2,
+        }
+    }
+}
+
+impl Pacing {
+    pub fn min_delay(&self) -> Duration {
+        Duration::from_millis(self.min_delay_ms)
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+
+    /// Gap between packets to a device that doesn't acknowledge them: the
+    /// whole timeout, as if waiting for an acknowledgement that never comes.
+    /// With the default that's the 300 ms every packet used to wait.
+    pub fn unacknowledged_delay(&self) -> Duration {
+        self.min_delay().max(self.timeout())
+    }
+}
+
+/// Minimum delays tried by `calibrate`, slowest first. 300 ms is what every
+/// packet used to wait.
+pub const CANDIDATES_MS: &[u64] = &[300, 200, 100, 50, 20, 10, 5, 2, 0];
+
+/// The pacing `calibrate` found for one unit.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Calibrated {
+    pub device: String,
+    /// Seconds since the Unix epoch.
+    pub calibrated_at: u64,
+    pub pacing: Pacing,
+    /// Slowest acknowledgement seen at that pacing, in microseconds.
+    pub max_latency_us: u64,
+}
+
+/// How one candidate delay did.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Step {
+    pub min_delay_ms: u64,
+    pub reliable: bool,
+    pub max_latency_us: u64,
+    pub error: Option<String>,
+}
+
+fn pacing_path(dir: &Path, device: &str) -> PathBuf {
+    dir.join(format!("{device}.pacing.json"))
+}
+
+pub fn load(dir: &Path, device: &str) -> Result<Option<Calibrated>> {
+    let path = pacing_path(dir, device);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+    };
+    serde_json::from_str(&text)
+        .map(Some)
+        .with_context(|| format!("parsing {}", path.display()))
+}
+
+pub fn save(dir: &Path, calibrated: &Calibrated) -> Result<PathBuf> {
+    fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    let path = pacing_path(dir, &calibrated.device);
+    fs::write(&path, serde_json::to_string_pretty(calibrated)?)
+        .with_context(|| format!("writing {}", path.display()))?;
+    Ok(path)
+}
+
+/// Writes `image` `rounds` times at each of `candidates` (minimum delays in
+/// ms, slowest first), going faster until a packet isn't handed back as sent
+/// the first time. Returns every step
+/// tried and the fastest pacing that held up, if any. Since a failed step
+/// can leave the device half-written, `image` is written once more at the
+/// chosen pacing (or `base` when none held up) before returning.
+pub fn calibrate(
+    dev: &impl Transport,
+    device: &str,
+    image: &ConfigImage,
+    candidates: &[u64],
+    base: Pacing,
+    rounds: usize,
+) -> Result<(Vec<Step>, Option<Calibrated>)> {
+    let mut steps = Vec::new();
+    let mut best: Option<(Pacing, u64)> = None;
+    for &min_delay_ms in candidates {
+        let pacing = Pacing {
+            min_delay_ms,
+            retries: 0,
+            ..base
+        };
+        say!("> Trying {min_delay_ms} ms");
+        let mut step = Step {
+            min_delay_ms,
+            reliable: true,
+            max_latency_us: 0,
+            error: None,
+        };
+        for _ in 0..rounds.max(1) {
+            let mut results = Vec::new();
+            let sent = device::send_report_to_mouse(image.to_reports(), dev, &pacing, &mut results);
+            step.max_latency_us = results
+                .iter()
+                .filter_map(|r| r.latency_us)
+                .fold(step.max_latency_us, u64::max);
+            if let Err(e) = sent {
+                step.reliable = false;
+                step.error = Some(format!("{e:#}"));
+                break;
+            }
+            if results.iter().any(|r| r.latency_us.is_none()) {
+                bail!(Error::Unsupported(
+                    "the device doesn't acknowledge packets, so there's nothing to calibrate"
+                        .into()
+                ));
+            }
+        }
+        let reliable = step.reliable;
+        if reliable {
+            best = Some((pacing, step.max_latency_us));
+        }
+        steps.push(step);
+        if !reliable {
+            break;
+        }
+    }
+
+    let pacing = best.map_or(base, |(p, _)| Pacing {
+        retries: base.retries,
+        ..p
+    });
+    if steps.last().is_some_and(|s| !s.reliable) {
+        say!("> Rewriting the config at {} ms", pacing.min_delay_ms);
+        device::send_report_to_mouse(image.to_reports(), dev, &pacing, &mut Vec::new())
+            .context("rewriting the config after calibration")?;
+    }
+    let calibrated = best.map(|(_, max_latency_us)| Calibrated {
+        device: device.to_owned(),
+        calibrated_at: crate::state::now(),
+        pacing,
+        max_latency_us,
+    });
+    Ok((steps, calibrated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MouseConfig;
+    use crate::image::PACKET_COUNT;
+    use crate::transport::mock::{MockTransport, Response};
+
+    fn base() -> Pacing {
+        Pacing {
+            min_delay_ms: 0,
+            timeout_ms: 0,
+            retries: 2,
+        }
+    }
+
+    #[test]
+    fn calibration_stops_at_the_first_unreliable_delay() {
+        let image = ConfigImage::encode(&MouseConfig::default());
+        let dev = MockTransport::new();
+        // One round per candidate: the third one drops a packet.
+        dev.fail_send(2 * PACKET_COUNT + 10);
+        let (steps, calibrated) =
+            calibrate(&dev, "1bcf-08a0", &image, &[3, 2, 1, 0], base(), 1).unwrap();
+        let tried: Vec<_> = steps.iter().map(|s| (s.min_delay_ms, s.reliable)).collect();
+        assert_eq!(tried, [(3, true), (2, true), (1, false)]);
+        let calibrated = calibrated.unwrap();
+        assert_eq!(calibrated.device, "1bcf-08a0");
+        assert_eq!(calibrated.pacing.min_delay_ms, 2);
+        assert_eq!(calibrated.pacing.retries, 2);
+        // The device is left with a complete image.
+        let sent = dev.sent();
+        assert_eq!(sent[sent.len() - PACKET_COUNT..], image.to_reports());
+    }
+
+    #[test]
+    fn calibration_needs_acknowledgements() {
+        let image = ConfigImage::encode(&MouseConfig::default());
+        let dev = MockTransport::new();
+        dev.respond(Response::Error("broken pipe"));
+        let err = calibrate(&dev, "1bcf-08a0", &image, &[3, 2], base(), 1).unwrap_err();
+        assert_eq!(crate::error::exit_code(&err), 9);
+    }
+
+    #[test]
+    fn calibration_round_trips() {
+        let dir = std::env::temp_dir().join(format!("redgear-a15-pacing-{}", std::process::id()));
+        let calibrated = Calibrated {
+            device: "1bcf-08a0".into(),
+            calibrated_at: 7,
+            pacing: base(),
+            max_latency_us: 1200,
+        };
+        assert_eq!(load(&dir, "1bcf-08a0").unwrap(), None);
+        save(&dir, &calibrated).unwrap();
+        assert_eq!(load(&dir, "1bcf-08a0").unwrap(), Some(calibrated));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,270 @@
+use crate::error::Error;
+use crate::{
+    BreathingSpeed, Commands, ContinouslyState, DpiVal, FireControl, GlobalMouseOptions, LedArgs,
+    LedBrightness, LedMode, LedStatus, MouseArgs, MouseConfig, ProfileCommand,
+};
+use anyhow::{Result, bail};
+use std::fmt::Debug;
+
+/// Every setting requested on the command line, wherever it appeared.
+/// Options can be given before the subcommand, after it, or both; giving the
+/// same option twice is fine as long as the values agree.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConfigPatch {
+    pub reset: bool,
+    pub repeat: Option<u8>,
+    pub firing_interval: Option<u8>,
+    pub continously: Option<ContinouslyState>,
+    pub moving_speed: Option<u8>,
+    pub double_click_speed: Option<u8>,
+    pub rolling_speed: Option<u8>,
+    pub led_brightness: Option<LedBrightness>,
+    pub breathing_speed: Option<BreathingSpeed>,
+    pub dpi: Option<DpiVal>,
+    pub led_mode: Option<LedMode>,
+    pub led_status: Option<LedStatus>,
+}
+
+fn merge<T: Copy + PartialEq + Debug>(
+    slot: &mut Option<T>,
+    name: &str,
+    value: Option<T>,
+) -> Result<()> {
+    match (*slot, value) {
+        (Some(old), Some(new)) if old != new => {
+            bail!(Error::InvalidSetting(format!(
+                "conflicting values for {name}: {old:?} and {new:?}"
+            )))
+        }
+        (_, Some(new)) => *slot = Some(new),
+        _ => {}
+    }
+    Ok(())
+}
+
+impl ConfigPatch {
+    pub fn from_args(args: &MouseArgs) -> Result<Self> {
+        let mut patch = Self::default();
+        patch.merge_options(&GlobalMouseOptions {
+            fire_control: args.fire_control.clone(),
+            moving_speed: args.moving_speed,
+            double_click_speed: args.double_click_speed,
+            rolling_speed: args.rolling_speed,
+            led_args: args.led_args.clone(),
+        })?;
+
+        match &args.command {
+            Some(Commands::Dpi { opts, dpi_val }) => {
+                merge(&mut patch.dpi, "dpi", Some(*dpi_val))?;
+                patch.merge_options(opts)?;
+            }
+            Some(Commands::Led { opts, mode, dpi }) => {
+                merge(&mut patch.led_mode, "led mode", Some(*mode))?;
+                merge(&mut patch.dpi, "dpi", *dpi)?;
+                patch.merge_options(opts)?;
+            }
+            Some(Commands::LedStatus { opts, state, dpi }) => {
+                merge(&mut patch.led_status, "led status", Some(*state))?;
+                merge(&mut patch.dpi, "dpi", *dpi)?;
+                patch.merge_options(opts)?;
+            }
+            Some(
+                Commands::Apply { opts, .. }
+                | Commands::Profile {
+                    action: ProfileCommand::Load { opts, .. },
+                },
+            ) => patch.merge_options(opts)?,
+            Some(Commands::Reset) => patch.reset = true,
+            Some(
+                Commands::Decode { .. }
+                | Commands::Status
+                | Commands::List
+                | Commands::History
+                | Commands::Calibrate { .. }
+                | Commands::Undo
+                | Commands::Revert { .. }
+                | Commands::Backup { .. }
+                | Commands::Restore { .. }
+                | Commands::Export { .. }
+                | Commands::Profile { .. },
+            )
+            | None => {}
+        }
+        Ok(patch)
+    }
+
+    fn merge_options(&mut self, opts: &GlobalMouseOptions) -> Result<()> {
+        if let Some(FireControl {
+            repeat,
+            firing_interval,
+            continously,
+        }) = &opts.fire_control
+        {
+            merge(&mut self.repeat, "repeat", *repeat)?;
+            merge(
+                &mut self.firing_interval,
+                "firing interval",
+                *firing_interval,
+            )?;
+            merge(&mut self.continously, "continously", *continously)?;
+        }
+        if let Some(LedArgs {
+            led_brightness,
+            breathing_speed,
+        }) = &opts.led_args
+        {
+            merge(&mut self.led_brightness, "led brightness", *led_brightness)?;
+            merge(
+                &mut self.breathing_speed,
+                "breathing speed",
+                *breathing_speed,
+            )?;
+        }
+        merge(&mut self.moving_speed, "moving speed", opts.moving_speed)?;
+        merge(
+            &mut self.double_click_speed,
+            "double-click speed",
+            opts.double_click_speed,
+        )?;
+        merge(&mut self.rolling_speed, "rolling speed", opts.rolling_speed)?;
+        Ok(())
+    }
+
+    /// Settings from `self`, with anything it leaves out taken from `fallback`.
+    pub fn or(self, fallback: ConfigPatch) -> Self {
+        Self {
+            reset: self.reset || fallback.reset,
+            repeat: self.repeat.or(fallback.repeat),
+            firing_interval: self.firing_interval.or(fallback.firing_interval),
+            continously: self.continously.or(fallback.continously),
+            moving_speed: self.moving_speed.or(fallback.moving_speed),
+            double_click_speed: self.double_click_speed.or(fallback.double_click_speed),
+            rolling_speed: self.rolling_speed.or(fallback.rolling_speed),
+            led_brightness: self.led_brightness.or(fallback.led_brightness),
+            breathing_speed: self.breathing_speed.or(fallback.breathing_speed),
+            dpi: self.dpi.or(fallback.dpi),
+            led_mode: self.led_mode.or(fallback.led_mode),
+            led_status: self.led_status.or(fallback.led_status),
+        }
+    }
+
+    /// Human-readable list of what the patch changes, in the order it's applied.
+    pub fn changes(&self) -> Vec<(String, String)> {
+        let mut changes: Vec<(String, String)> = Vec::new();
+        if self.reset {
+            changes.push(("Reset".into(), "Factory Defaults".into()));
+        }
+        if let Some(rep) = self.repeat {
+            changes.push(("Repeat".into(), rep.to_string()));
+        }
+        if let Some(intv) = self.firing_interval {
+            changes.push(("Firing Interval".into(), intv.to_string()));
+        }
+        if let Some(cont) = self.continously {
+            changes.push(("Continously".into(), format!("{:?}", cont)));
+        }
+        if let Some(br) = self.led_brightness {
+            changes.push(("LED Brightness".into(), format!("{:?}", br)));
+        }
+        if let Some(bs) = self.breathing_speed {
+            changes.push(("LED Breathing Speed".into(), format!("{:?}", bs)));
+        }
+        if let Some(dpi) = self.dpi {
+            changes.push(("DPI".into(), format!("{:?}", dpi)));
+        }
+        if let Some(mode) = self.led_mode {
+            changes.push(("LED Mode".into(), format!("{:?}", mode)));
+        }
+        if let Some(state) = self.led_status {
+            changes.push(("LED Status".into(), format!("{:?}", state)));
+        }
+        changes
+    }
+
+    /// Layers the patch over `base`. `reset` swaps in the factory values first,
+    /// so `reset` combined with other options applies those on top.
+    pub fn apply(&self, base: &MouseConfig) -> MouseConfig {
+        let mut cfg = if self.reset {
+            MouseConfig::factory()
+        } else {
+            base.clone()
+        };
+        if let Some(rep) = self.repeat {
+            cfg.repeat = rep;
+        }
+        if let Some(intv) = self.firing_interval {
+            cfg.firing_interval = intv;
+        }
+        if let Some(cont) = self.continously {
+            cfg.continously = cont;
+        }
+        if let Some(br) = self.led_brightness {
+            cfg.led_args.led_brightness = Some(br);
+        }
+        if let Some(bs) = self.breathing_speed {
+            cfg.led_args.breathing_speed = Some(bs);
+        }
+        if let Some(dpi) = self.dpi {
+            cfg.dpi = dpi;
+        }
+        if let Some(mode) = self.led_mode {
+            cfg.led_mode = mode;
+        }
+        if let Some(state) = self.led_status {
+            cfg.led_status = state;
+        }
+        cfg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn patch(argv: &[&str]) -> Result<ConfigPatch> {
+        let args =
+            MouseArgs::try_parse_from(std::iter::once("redgear-a15").chain(argv.iter().copied()))?;
+        ConfigPatch::from_args(&args)
+    }
+
+    #[test]
+    fn subcommand_options_are_merged() {
+        let p = patch(&["dpi", "3", "-r", "5", "--led-brightness", "Half"]).unwrap();
+        assert_eq!(p.dpi, Some(DpiVal::DPI3));
+        assert_eq!(p.repeat, Some(5));
+        assert_eq!(p.led_brightness, Some(LedBrightness::Half));
+    }
+
+    #[test]
+    fn options_on_both_sides_of_the_subcommand_are_merged() {
+        let p = patch(&[
+            "-f",
+            "9",
+            "led",
+            "rainbow",
+            "--breathing-speed",
+            "2",
+            "-f",
+            "9",
+        ])
+        .unwrap();
+        assert_eq!(p.firing_interval, Some(9));
+        assert_eq!(p.led_mode, Some(LedMode::Rainbow));
+        assert_eq!(p.breathing_speed, Some(BreathingSpeed::BS2));
+    }
+
+    #[test]
+    fn conflicting_values_are_rejected() {
+        let err = patch(&["-r", "3", "dpi", "2", "-r", "5"]).unwrap_err();
+        assert!(err.to_string().contains("repeat"), "{err}");
+    }
+
+    #[test]
+    fn reset_applies_other_options_on_top() {
+        let p = patch(&["-r", "9", "reset"]).unwrap();
+        let cfg = p.apply(&MouseConfig::default());
+        assert_eq!(cfg.repeat, 9);
+        assert_eq!(cfg.dpi, MouseConfig::factory().dpi);
+    }
+}
@@ -0,0 +1,309 @@
+use crate::MouseConfig;
+use crate::error::Error;
+use crate::patch::ConfigPatch;
+use crate::{BreathingSpeed, ContinouslyState, DpiVal, LedBrightness, LedMode, LedStatus};
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A saved set of settings. Every field is optional; whatever is left out
+/// keeps its current value when the profile is applied.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// DPI level, 1–8.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dpi: Option<DpiVal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub led_mode: Option<LedMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub led_status: Option<LedStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub led_brightness: Option<LedBrightness>,
+    /// Breathing speed, 1–8.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub breathing_speed: Option<BreathingSpeed>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub firing_interval: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub continously: Option<ContinouslyState>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Toml,
+    Json,
+}
+
+impl Format {
+    /// `.json` files are JSON, anything else TOML.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => Format::Json,
+            _ => Format::Toml,
+        }
+    }
+}
+
+impl Profile {
+    /// Every setting of `cfg`.
+    pub fn from_config(cfg: &MouseConfig) -> Self {
+        Self {
+            dpi: Some(cfg.dpi),
+            led_mode: Some(cfg.led_mode),
+            led_status: Some(cfg.led_status),
+            led_brightness: Some(cfg.led_args.led_brightness.unwrap_or_default()),
+            breathing_speed: Some(cfg.led_args.breathing_speed.unwrap_or_default()),
+            repeat: Some(cfg.repeat),
+            firing_interval: Some(cfg.firing_interval),
+            continously: Some(cfg.continously),
+        }
+    }
+
+    pub fn patch(&self) -> ConfigPatch {
+        ConfigPatch {
+            dpi: self.dpi,
+            led_mode: self.led_mode,
+            led_status: self.led_status,
+            led_brightness: self.led_brightness,
+            breathing_speed: self.breathing_speed,
+            repeat: self.repeat,
+            firing_interval: self.firing_interval,
+            continously: self.continously,
+            ..ConfigPatch::default()
+        }
+    }
+
+    pub fn parse(text: &str, format: Format) -> Result<Self> {
+        let profile: Self = match format {
+            Format::Toml => {
+                toml::from_str(text).map_err(|e| Error::InvalidSetting(e.to_string()))?
+            }
+            Format::Json => {
+                serde_json::from_str(text).map_err(|e| Error::InvalidSetting(e.to_string()))?
+            }
+        };
+        if profile == Self::default() {
+            bail!(Error::InvalidSetting("profile doesn't set anything".into()));
+        }
+        Ok(profile)
+    }
+
+    /// One-line description for `profile list`.
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(dpi) = self.dpi {
+            parts.push(format!("DPI {}", u8::from(dpi)));
+        }
+        if let Some(mode) = self.led_mode {
+            parts.push(format!("LED {mode:?}"));
+        }
+        if let Some(state) = self.led_status {
+            parts.push(format!("LED {state:?}"));
+        }
+        if let Some(br) = self.led_brightness {
+            parts.push(format!("brightness {br:?}"));
+        }
+        if let Some(bs) = self.breathing_speed {
+            parts.push(format!("breathing {}", u8::from(bs)));
+        }
+        if let Some(rep) = self.repeat {
+            parts.push(format!("repeat {rep}"));
+        }
+        if let Some(intv) = self.firing_interval {
+            parts.push(format!("interval {intv}"));
+        }
+        if let Some(cont) = self.continously {
+            parts.push(format!("continuous {cont:?}"));
+        }
+        parts.join(", ")
+    }
+
+    pub fn render(&self, format: Format) -> Result<String> {
+        Ok(match format {
+            Format::Toml => toml::to_string(self)?,
+            Format::Json => serde_json::to_string_pretty(self)? + "\n",
+        })
+    }
+}
+
+/// Reads and validates a profile file; the format follows the extension.
+pub fn load(path: &Path) -> Result<Profile> {
+    let text = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    Profile::parse(&text, Format::from_path(path))
+        .with_context(|| format!("invalid profile {}", path.display()))
+}
+
+/// `$XDG_CONFIG_HOME/redgear-a15/profiles`.
+pub fn store_dir() -> Result<PathBuf> {
+    Ok(crate::config::config_dir()?.join("profiles"))
+}
+
+/// Path of the named profile in `dir`. Names become file names, so they're
+/// limited to letters, digits, `-` and `_`.
+pub fn store_path(dir: &Path, name: &str) -> Result<PathBuf> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!(Error::InvalidSetting(format!(
+            "invalid profile name `{name}`, use letters, digits, `-` and `_`"
+        )));
+    }
+    Ok(dir.join(format!("{name}.toml")))
+}
+
+/// Writes `profile` under `name`, refusing to replace an existing one unless
+/// `force` is set.
+pub fn save_named(dir: &Path, name: &str, profile: &Profile, force: bool) -> Result<PathBuf> {
+    let path = store_path(dir, name)?;
+    if !force && path.exists() {
+        bail!(Error::InvalidSetting(format!(
+            "profile `{name}` already exists, use --force to replace it"
+        )));
+    }
+    fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    fs::write(&path, profile.render(Format::Toml)?)
+        .with_context(|| format!("writing {}", path.display()))?;
+    Ok(path)
+}
+
+pub fn load_named(dir: &Path, name: &str) -> Result<Profile> {
+    let path = store_path(dir, name)?;
+    if !path.exists() {
+        bail!(Error::InvalidSetting(format!(
+            "no profile named `{name}`, see `profile list`"
+        )));
+    }
+    load(&path)
+}
+
+pub fn delete_named(dir: &Path, name: &str) -> Result<()> {
+    let path = store_path(dir, name)?;
+    match fs::remove_file(&path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            bail!(Error::InvalidSetting(format!(
+                "no profile named `{name}`, see `profile list`"
+            )))
+        }
+        res => res.with_context(|| format!("deleting {}", path.display())),
+    }
+}
+
+/// Every stored profile by name, in name order. A profile that fails to
+/// parse is listed with its error instead of hiding the others.
+pub fn list_named(dir: &Path) -> Result<Vec<(String, Result<Profile>)>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", dir.display())),
+    };
+    let mut profiles = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "toml")
+            && let Some(name) = path.file_stem().and_then(|n| n.to_str())
+        {
+            profiles.push((name.to_owned(), load(&path)));
+        }
+    }
+    profiles.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(profiles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_profile_round_trips_in_both_formats() {
+        let cfg = MouseConfig {
+            dpi: DpiVal::DPI7,
+            led_mode: LedMode::FourSeasons,
+            continously: ContinouslyState::Enable,
+            ..MouseConfig::factory()
+        };
+        let profile = Profile::from_config(&cfg);
+        for format in [Format::Toml, Format::Json] {
+            let text = profile.render(format).unwrap();
+            assert_eq!(Profile::parse(&text, format).unwrap(), profile, "{text}");
+        }
+        let applied = profile.patch().apply(&MouseConfig::default());
+        assert_eq!(applied.dpi, cfg.dpi);
+        assert_eq!(applied.led_mode, cfg.led_mode);
+        assert_eq!(applied.led_args, cfg.led_args);
+        assert_eq!(applied.repeat, cfg.repeat);
+    }
+
+    #[test]
+    fn toml_profile_uses_cli_spellings() {
+        let text = "dpi = 3\nled_mode = \"floe-light\"\nled_brightness = \"Half\"\n";
+        let profile = Profile::parse(text, Format::Toml).unwrap();
+        assert_eq!(profile.dpi, Some(DpiVal::DPI3));
+        assert_eq!(profile.led_mode, Some(LedMode::FloeLight));
+        assert_eq!(profile.led_brightness, Some(LedBrightness::Half));
+        assert_eq!(profile.repeat, None);
+    }
+
+    #[test]
+    fn invalid_profiles_are_rejected() {
+        for text in ["dpi = 9\n", "dpi = 3\nspeed = 1\n", "repeat = 300\n", ""] {
+            assert!(Profile::parse(text, Format::Toml).is_err(), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn format_follows_the_extension() {
+        assert_eq!(Format::from_path(Path::new("fps.JSON")), Format::Json);
+        assert_eq!(Format::from_path(Path::new("fps.toml")), Format::Toml);
+        assert_eq!(Format::from_path(Path::new("fps")), Format::Toml);
+    }
+
+    #[test]
+    fn store_saves_lists_and_deletes() {
+        let dir = std::env::temp_dir().join(format!("redgear-a15-profiles-{}", std::process::id()));
+        let fps = Profile {
+            dpi: Some(DpiVal::DPI8),
+            led_mode: Some(LedMode::Off),
+            ..Profile::default()
+        };
+        assert!(list_named(&dir).unwrap().is_empty());
+        save_named(&dir, "fps", &fps, false).unwrap();
+        let exists = save_named(&dir, "fps", &fps, false).unwrap_err();
+        assert_eq!(crate::error::exit_code(&exists), 8);
+        save_named(&dir, "fps", &fps, true).unwrap();
+        assert_eq!(load_named(&dir, "fps").unwrap(), fps);
+
+        let names: Vec<_> = list_named(&dir)
+            .unwrap()
+            .into_iter()
+            .map(|(name, p)| (name, p.unwrap().summary()))
+            .collect();
+        assert_eq!(names, [("fps".to_owned(), "DPI 8, LED Off".to_owned())]);
+
+        delete_named(&dir, "fps").unwrap();
+        assert!(delete_named(&dir, "fps").is_err());
+        let missing = load_named(&dir, "fps").unwrap_err();
+        assert_eq!(crate::error::exit_code(&missing), 8);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn profile_names_cannot_escape_the_store() {
+        let dir = Path::new("/store");
+        assert!(store_path(dir, "../x").is_err());
+        assert!(store_path(dir, "").is_err());
+        assert_eq!(
+            store_path(dir, "fps_2").unwrap(),
+            Path::new("/store/fps_2.toml")
+        );
+    }
+}
@@ -0,0 +1,674 @@
+use crate::config::FileConfig;
+use crate::error::Error;
+use crate::{PID, VID, descriptor, image, state};
+use anyhow::{Result, anyhow, bail};
+use hidapi::{DeviceInfo, HidApi, HidDevice};
+use std::{ffi::CString, fmt, str::FromStr};
+
+/// Usage pages 0xff00..=0xffff are vendor-defined.
+const VENDOR_USAGE_PAGE: u16 = 0xff00;
+
+const LIST_HINT: &str = "run `redgear-a15 list` to see what's connected";
+
+/// A `VID:PID` pair as given on the command line, e.g. `1bcf:08a0`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeviceId {
+    pub vid: u16,
+    pub pid: u16,
+}
+
+impl Default for DeviceId {
+    fn default() -> Self {
+        Self { vid: VID, pid: PID }
+    }
+}
+
+impl fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04x}:{:04x}", self.vid, self.pid)
+    }
+}
+
+impl FromStr for DeviceId {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let hex = |part: &str| {
+            let part = part.trim();
+            let part = part
+                .strip_prefix("0x")
+                .or_else(|| part.strip_prefix("0X"))
+                .unwrap_or(part);
+            u16::from_str_radix(part, 16).map_err(|_| format!("`{s}` is not VID:PID in hex"))
+        };
+        let (vid, pid) = s
+            .split_once(':')
+            .ok_or_else(|| format!("`{s}` is not VID:PID, e.g. 1bcf:08a0"))?;
+        Ok(Self {
+            vid: hex(vid)?,
+            pid: hex(pid)?,
+        })
+    }
+}
+
+/// Which unit to talk to. Built from the command line, the environment and
+/// the config file, in that order of precedence.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Selector {
+    pub id: DeviceId,
+    pub path: Option<String>,
+    pub serial: Option<String>,
+    pub interface: Option<i32>,
+}
+
+impl Selector {
+    /// `device`, `path`, `serial` and `interface` already carry the flag or
+    /// environment value (clap handles both); the config file only fills in
+    /// the gaps.
+    pub fn resolve(
+        device: Option<DeviceId>,
+        path: Option<&str>,
+        serial: Option<&str>,
+        interface: Option<i32>,
+        file: &FileConfig,
+    ) -> Result<Self> {
+        let id = match device {
+            Some(id) => id,
+            None => match &file.device {
+                Some(s) => s.parse().map_err(|e| anyhow!("config file: device: {e}"))?,
+                None => DeviceId::default(),
+            },
+        };
+        Ok(Self {
+            id,
+            path: path.or(file.path.as_deref()).map(str::to_owned),
+            serial: serial.or(file.serial.as_deref()).map(str::to_owned),
+            interface: interface.or(file.interface),
+        })
+    }
+}
+
+/// The parts of a `hidapi::DeviceInfo` needed to pick a device, so the
+/// selection rules can be tested without real hardware.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Candidate {
+    pub id: DeviceId,
+    pub path: String,
+    pub serial: Option<String>,
+    pub interface: i32,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub usage_page: u16,
+    pub usage: u16,
+    /// The physical unit the interface belongs to, when the platform says;
+    /// see `unit_of`.
+    pub unit: Option<String>,
+}
+
+impl Candidate {
+    pub fn from_info(info: &DeviceInfo) -> Self {
+        Self {
+            id: DeviceId {
+                vid: info.vendor_id(),
+                pid: info.product_id(),
+            },
+            path: info.path().to_string_lossy().into_owned(),
+            serial: info
+                .serial_number()
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_owned),
+            interface: info.interface_number(),
+            manufacturer: info.manufacturer_string().map(str::to_owned),
+            product: info.product_string().map(str::to_owned),
+            usage_page: info.usage_page(),
+            usage: info.usage(),
+            unit: unit_of(&info.path().to_string_lossy()),
+        }
+    }
+
+    /// Whether `other` is an interface of the same mouse. Without a known
+    /// unit only the serial can tell, which identical mice often share.
+    pub fn same_unit(&self, other: &Candidate) -> bool {
+        self.serial == other.serial
+            && match (&self.unit, &other.unit) {
+                (Some(a), Some(b)) => a == b,
+                _ => true,
+            }
+    }
+
+    pub fn describe(&self) -> String {
+        match &self.serial {
+            Some(serial) => format!("{} serial {serial} at {}", self.id, self.path),
+            None => format!("{} at {}", self.id, self.path),
+        }
+    }
+}
+
+/// The USB device behind an interface path, i.e. the path without its
+/// interface number. libusb paths carry it (`1-2:1.0` → `1-2:1`); hidraw
+/// nodes are looked up in sysfs, where they sit below the USB interface
+/// (`.../1-2/1-2:1.1/0003:1BCF:08A0.0005/hidraw/hidraw3`). `None` when
+/// neither applies.
+fn unit_of(path: &str) -> Option<String> {
+    if let Some(unit) = strip_interface(path) {
+        return Some(unit.to_owned());
+    }
+    let node = path.strip_prefix("/dev/")?;
+    let sys = std::fs::canonicalize(format!("/sys/class/hidraw/{node}/device")).ok()?;
+    sys.iter()
+        .rev()
+        .find_map(|part| part.to_str().and_then(strip_interface))
+        .map(str::to_owned)
+}
+
+/// `1-2.4:1.0` → `1-2.4:1`: a USB interface name (bus-ports:config.interface)
+/// without the interface number.
+fn strip_interface(name: &str) -> Option<&str> {
+    let (ports, rest) = name.split_once(':')?;
+    let (config, interface) = rest.split_once('.')?;
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let ports_ok = ports
+        .bytes()
+        .all(|b| b.is_ascii_digit() || b == b'-' || b == b'.');
+    (ports_ok && digits(config) && digits(interface))
+        .then(|| &name[..ports.len() + 1 + config.len()])
+}
+
+/// Interfaces matching the VID/PID and serial of `sel`, ordered by serial,
+/// interface number and path.
+fn matching<'a>(candidates: &'a [Candidate], sel: &Selector) -> Result<Vec<&'a Candidate>> {
+    let mut matching: Vec<&Candidate> = candidates
+        .iter()
+        .filter(|c| c.id == sel.id)
+        .filter(|c| sel.serial.is_none() || c.serial == sel.serial)
+        .collect();
+    matching
+        .sort_by(|a, b| (&a.serial, a.interface, &a.path).cmp(&(&b.serial, b.interface, &b.path)));
+    if matching.is_empty() {
+        match &sel.serial {
+            Some(serial) => bail!(Error::DeviceNotFound(format!(
+                "no device {} with serial {serial} found, {LIST_HINT}",
+                sel.id
+            ))),
+            None => bail!(Error::DeviceNotFound(format!(
+                "no device {} found, {LIST_HINT}",
+                sel.id
+            ))),
+        }
+    }
+    Ok(matching)
+}
+
+/// Picks a unit out of `candidates` and returns its interfaces. A `path`
+/// selects exactly that node. Otherwise matches are ordered by serial,
+/// interface number and path and the first unit wins, so the same set of
+/// plugged-in mice always gives the same answer. The second value lists one
+/// interface of every other unit that also matched.
+pub fn choose<'a>(
+    candidates: &'a [Candidate],
+    sel: &Selector,
+) -> Result<(Vec<&'a Candidate>, Vec<&'a Candidate>)> {
+    if let Some(path) = &sel.path {
+        let found = candidates.iter().find(|c| &c.path == path).ok_or_else(|| {
+            Error::DeviceNotFound(format!("no HID device at {path}, {LIST_HINT}"))
+        })?;
+        if let Some(serial) = &sel.serial
+            && found.serial.as_ref() != Some(serial)
+        {
+            bail!(Error::DeviceNotFound(format!(
+                "{path} does not have serial {serial}, {LIST_HINT}"
+            )));
+        }
+        return Ok((vec![found], Vec::new()));
+    }
+
+    let mut units = units(matching(candidates, sel)?);
+    let unit = units.remove(0);
+    Ok((unit, units.into_iter().map(|u| u[0]).collect()))
+}
+
+/// `candidates` grouped by the mouse they belong to (see
+/// `Candidate::same_unit`), in the order each mouse first appears.
+pub fn units<'a>(candidates: impl IntoIterator<Item = &'a Candidate>) -> Vec<Vec<&'a Candidate>> {
+    let mut units: Vec<Vec<&Candidate>> = Vec::new();
+    for c in candidates {
+        match units.iter_mut().find(|u| u[0].same_unit(c)) {
+            Some(unit) => unit.push(c),
+            None => units.push(vec![c]),
+        }
+    }
+    units
+}
+
+/// Feature report IDs declared by the report descriptor of `c`, or why they
+/// couldn't be read.
+pub fn feature_reports(api: &HidApi, c: &Candidate) -> std::result::Result<Vec<u8>, String> {
+    let dev = api
+        .open_path(&CString::new(c.path.as_str()).map_err(|e| e.to_string())?)
+        .map_err(|e| e.to_string())?;
+    let mut buf = [0u8; hidapi::MAX_REPORT_DESCRIPTOR_SIZE];
+    let len = dev
+        .get_report_descriptor(&mut buf)
+        .map_err(|e| e.to_string())?;
+    Ok(descriptor::feature_report_ids(&buf[..len]))
+}
+
+/// Picks the interface of one unit that config packets go to: the one given
+/// by `--interface`, else the first whose report descriptor declares feature
+/// report 0x04, else the first on a vendor-defined usage page. A unit with a
+/// single interface is used as is when its descriptor couldn't be read.
+/// `reports` holds `feature_reports` for each entry of `unit`.
+pub fn pick_interface<'a>(
+    unit: &[&'a Candidate],
+    reports: &[std::result::Result<Vec<u8>, String>],
+    interface: Option<i32>,
+) -> Result<&'a Candidate> {
+    if let Some(n) = interface {
+        if let Some(&c) = unit.iter().find(|c| c.interface == n) {
+            return Ok(c);
+        }
+    } else {
+        let declares_config = |r: &std::result::Result<Vec<u8>, String>| {
+            r.as_ref().is_ok_and(|ids| ids.contains(&image::REPORT_ID))
+        };
+        if let Some((&c, _)) = unit.iter().zip(reports).find(|(_, r)| declares_config(r)) {
+            return Ok(c);
+        }
+        if let Some(&c) = unit.iter().find(|c| c.usage_page >= VENDOR_USAGE_PAGE) {
+            return Ok(c);
+        }
+        if let ([c], [Err(_)]) = (unit, reports) {
+            return Ok(c);
+        }
+    }
+
+    let mut msg = match interface {
+        Some(n) => format!("{} has no interface {n}; found:", unit[0].id),
+        None => format!(
+            "no interface of {} declares feature report 0x{:02x}; found:",
+            unit[0].id,
+            image::REPORT_ID
+        ),
+    };
+    for (c, r) in unit.iter().zip(reports) {
+        let ids = match r {
+            Ok(ids) if ids.is_empty() => "no feature reports".to_owned(),
+            Ok(ids) => format!(
+                "feature reports {}",
+                ids.iter()
+                    .map(|id| format!("0x{id:02x}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Err(e) => format!("descriptor not readable: {e}"),
+        };
+        msg.push_str(&format!(
+            "\n  interface {}  usage {:04x}:{:04x}  {}  {ids}",
+            c.interface, c.usage_page, c.usage, c.path
+        ));
+    }
+    msg.push_str("\nuse --interface or --path to pick one");
+    Err(Error::WrongInterface(msg).into())
+}
+
+/// The config interface of every unit matching `sel`, for `--all-devices`:
+/// the interfaces are grouped by `units` and each unit's is picked by
+/// `pick_interface`, as for a single mouse.
+pub fn all_config_interfaces<'a>(
+    candidates: &'a [Candidate],
+    sel: &Selector,
+    reports: impl Fn(&Candidate) -> std::result::Result<Vec<u8>, String>,
+) -> Result<Vec<&'a Candidate>> {
+    if sel.path.is_some() {
+        return Ok(choose(candidates, sel)?.0);
+    }
+    let mut found = Vec::new();
+    for unit in units(matching(candidates, sel)?) {
+        let unit_reports: Vec<_> = unit.iter().map(|c| reports(c)).collect();
+        found.push(pick_interface(&unit, &unit_reports, sel.interface)?);
+    }
+    Ok(found)
+}
+
+/// What tells `c` apart from the other mice in `candidates` (every HID
+/// interface found): its serial, with its USB port added (or its path, where
+/// the port isn't known) only when another mouse with the same VID/PID has
+/// the same serial or, like `c`, none. `None` for a lone mouse without one.
+pub fn unit_name(c: &Candidate, candidates: &[Candidate]) -> Option<String> {
+    let alike = candidates
+        .iter()
+        .filter(|o| o.id == c.id && o.serial == c.serial);
+    if units(alike).len() < 2 {
+        return c.serial.clone();
+    }
+    let port = c.unit.as_deref().unwrap_or(&c.path);
+    Some(match &c.serial {
+        Some(serial) => format!("{serial} at {port}"),
+        None => port.to_owned(),
+    })
+}
+
+/// The key state, history and calibrated pacing of `c` are saved under:
+/// `state::device_key` with `unit_name` in place of the serial.
+pub fn device_key(c: &Candidate, candidates: &[Candidate]) -> String {
+    state::device_key(c.id.vid, c.id.pid, unit_name(c, candidates).as_deref())
+}
+
+/// Opens one interface found by `choose` or `all_config_interfaces`.
+pub fn open_candidate(api: &HidApi, c: &Candidate) -> Result<HidDevice> {
+    let path = CString::new(c.path.as_str())?;
+    api.open_path(&path).map_err(|e| {
+        let msg = e.to_string();
+        if msg.to_lowercase().contains("permission denied") {
+            Error::PermissionDenied(format!(
+                "opening {}: {msg}; give your user access to the hidraw node (a udev rule) or run as root",
+                c.describe()
+            ))
+            .into()
+        } else {
+            anyhow!(e).context(format!(
+                "opening {} (run `redgear-a15 list` to see its interfaces)",
+                c.describe()
+            ))
+        }
+    })
+}
+
+/// Opens the interface picked by `sel`, returning it with what it was
+/// picked as and its `device_key`. An explicit `--path` is used as is.
+pub fn open(api: &HidApi, sel: &Selector) -> Result<(HidDevice, Candidate, String)> {
+    let candidates: Vec<Candidate> = api.device_list().map(Candidate::from_info).collect();
+    let (unit, others) = choose(&candidates, sel)?;
+    if !others.is_empty() {
+        eprintln!(
+            "{}WARN:{} {} matching devices, using {}; pick another with --serial or --path:",
+            crate::YELLOW,
+            crate::RESET,
+            others.len() + 1,
+            unit[0].describe()
+        );
+        for other in &others {
+            eprintln!("  {}", other.describe());
+        }
+        eprintln!("  (or use --all-devices to configure all of them)");
+    }
+    let chosen = if sel.path.is_some() {
+        unit[0]
+    } else {
+        let reports: Vec<_> = unit.iter().map(|c| feature_reports(api, c)).collect();
+        pick_interface(&unit, &reports, sel.interface)?
+    };
+    let key = device_key(chosen, &candidates);
+    Ok((open_candidate(api, chosen)?, chosen.clone(), key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(path: &str, serial: Option<&str>, interface: i32) -> Candidate {
+        Candidate {
+            id: DeviceId::default(),
+            path: path.into(),
+            serial: serial.map(str::to_owned),
+            interface,
+            manufacturer: None,
+            product: None,
+            usage_page: 0,
+            usage: 0,
+            unit: None,
+        }
+    }
+
+    #[test]
+    fn parses_vid_pid() {
+        assert_eq!(
+            "1bcf:08a0".parse::<DeviceId>().unwrap(),
+            DeviceId {
+                vid: 0x1bcf,
+                pid: 0x08a0
+            }
+        );
+        assert_eq!("0x1BCF:0x08A1".parse::<DeviceId>().unwrap().pid, 0x08a1);
+        assert!("1bcf".parse::<DeviceId>().is_err());
+        assert!("1bcf:zz".parse::<DeviceId>().is_err());
+    }
+
+    #[test]
+    fn flags_override_the_config_file() {
+        let file = FileConfig {
+            device: Some("1bcf:0001".into()),
+            path: None,
+            serial: Some("from-file".into()),
+            interface: Some(1),
+        };
+        let sel = Selector::resolve(None, None, Some("from-flag"), None, &file).unwrap();
+        assert_eq!(sel.id.pid, 0x0001);
+        assert_eq!(sel.serial.as_deref(), Some("from-flag"));
+        assert_eq!(sel.interface, Some(1));
+    }
+
+    #[test]
+    fn choice_does_not_depend_on_enumeration_order() {
+        let mut list = vec![
+            candidate("/dev/hidraw5", Some("B"), 1),
+            candidate("/dev/hidraw3", Some("A"), 1),
+            candidate("/dev/hidraw2", Some("A"), 0),
+            candidate("/dev/hidraw4", Some("B"), 0),
+        ];
+        let sel = Selector::default();
+        let (unit, others) = choose(&list, &sel).unwrap();
+        let paths: Vec<_> = unit.iter().map(|c| c.path.clone()).collect();
+        assert_eq!(paths, ["/dev/hidraw2", "/dev/hidraw3"]);
+        assert_eq!(others.len(), 1);
+        list.reverse();
+        assert_eq!(choose(&list, &sel).unwrap().0[0].path, paths[0]);
+    }
+
+    #[test]
+    fn identical_units_without_a_serial_are_told_apart() {
+        let on = |path: &str, interface, unit: &str| Candidate {
+            unit: Some(unit.into()),
+            ..candidate(path, None, interface)
+        };
+        let list = [
+            on("/dev/hidraw2", 0, "1-2:1"),
+            on("/dev/hidraw3", 1, "1-2:1"),
+            on("/dev/hidraw6", 0, "1-4:1"),
+            on("/dev/hidraw7", 1, "1-4:1"),
+        ];
+        let (unit, others) = choose(&list, &Selector::default()).unwrap();
+        let paths: Vec<_> = unit.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(paths, ["/dev/hidraw2", "/dev/hidraw3"]);
+        assert_eq!(others.len(), 1);
+        assert_eq!(others[0].path, "/dev/hidraw6");
+    }
+
+    #[test]
+    fn listing_shows_each_unit_once() {
+        let on = |path: &str, interface, unit: &str| Candidate {
+            unit: Some(unit.into()),
+            ..candidate(path, None, interface)
+        };
+        let list = [
+            on("/dev/hidraw2", 0, "1-2:1"),
+            on("/dev/hidraw6", 0, "1-4:1"),
+            on("/dev/hidraw3", 1, "1-2:1"),
+            on("/dev/hidraw7", 1, "1-4:1"),
+            candidate("/dev/hidraw4", Some("B"), 0),
+        ];
+        let paths: Vec<Vec<&str>> = units(&list)
+            .iter()
+            .map(|u| u.iter().map(|c| c.path.as_str()).collect())
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                vec!["/dev/hidraw2", "/dev/hidraw3"],
+                vec!["/dev/hidraw6", "/dev/hidraw7"],
+                vec!["/dev/hidraw4"],
+            ]
+        );
+    }
+
+    #[test]
+    fn unit_is_the_path_without_its_interface() {
+        assert_eq!(strip_interface("1-2:1.0"), Some("1-2:1"));
+        assert_eq!(strip_interface("3-1.4:1.2"), Some("3-1.4:1"));
+        assert_eq!(strip_interface("0003:1BCF:08A0.0005"), None);
+        assert_eq!(strip_interface("usb1"), None);
+        assert_eq!(unit_of("1-2:1.1").as_deref(), Some("1-2:1"));
+        assert_eq!(unit_of("DevSrvsID:4294969282"), None);
+    }
+
+    #[test]
+    fn serial_and_path_narrow_the_choice() {
+        let list = [
+            candidate("/dev/hidraw2", Some("A"), 0),
+            candidate("/dev/hidraw4", Some("B"), 0),
+        ];
+        let by_serial = Selector {
+            serial: Some("B".into()),
+            ..Selector::default()
+        };
+        assert_eq!(choose(&list, &by_serial).unwrap().0[0].path, "/dev/hidraw4");
+
+        let by_path = Selector {
+            path: Some("/dev/hidraw4".into()),
+            ..Selector::default()
+        };
+        assert_eq!(choose(&list, &by_path).unwrap().0[0].path, "/dev/hidraw4");
+
+        let wrong = Selector {
+            serial: Some("C".into()),
+            ..Selector::default()
+        };
+        assert!(choose(&list, &wrong).is_err());
+    }
+
+    #[test]
+    fn interface_declaring_report_4_wins() {
+        let mouse = candidate("/dev/hidraw2", None, 0);
+        let vendor = candidate("/dev/hidraw3", None, 1);
+        let unit = [&mouse, &vendor];
+        let reports = [Ok(vec![]), Ok(vec![1, 4])];
+        assert_eq!(pick_interface(&unit, &reports, None).unwrap(), &vendor);
+        assert_eq!(pick_interface(&unit, &reports, Some(0)).unwrap(), &mouse);
+    }
+
+    #[test]
+    fn vendor_usage_page_is_the_fallback() {
+        let mouse = candidate("/dev/hidraw2", None, 0);
+        let vendor = Candidate {
+            usage_page: 0xff00,
+            ..candidate("/dev/hidraw3", None, 1)
+        };
+        let reports = [Err("denied".into()), Err("denied".into())];
+        assert_eq!(
+            pick_interface(&[&mouse, &vendor], &reports, None).unwrap(),
+            &vendor
+        );
+    }
+
+    #[test]
+    fn no_config_interface_lists_what_was_found() {
+        let mouse = candidate("/dev/hidraw2", None, 0);
+        let keys = candidate("/dev/hidraw3", None, 1);
+        let reports = [Ok(vec![]), Err("permission denied".into())];
+        let err = pick_interface(&[&mouse, &keys], &reports, None)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("/dev/hidraw2  no feature reports"), "{err}");
+        assert!(err.contains("permission denied"), "{err}");
+        assert!(pick_interface(&[&mouse, &keys], &reports, Some(5)).is_err());
+    }
+
+    #[test]
+    fn all_devices_finds_every_unit() {
+        let on = |path: &str, serial: Option<&str>, interface, unit: &str| Candidate {
+            unit: Some(unit.into()),
+            ..candidate(path, serial, interface)
+        };
+        // Two mice without a serial, one more whose interfaces both declare
+        // report 0x04, and one with a serial.
+        let list = [
+            on("/dev/hidraw2", None, 0, "1-2:1"),
+            on("/dev/hidraw3", None, 1, "1-2:1"),
+            on("/dev/hidraw6", None, 0, "1-4:1"),
+            on("/dev/hidraw7", None, 1, "1-4:1"),
+            on("/dev/hidraw8", None, 0, "1-6:1"),
+            on("/dev/hidraw9", None, 1, "1-6:1"),
+            on("/dev/hidraw4", Some("B"), 0, "1-3:1"),
+            on("/dev/hidraw5", Some("B"), 1, "1-3:1"),
+        ];
+        let vendor = |c: &Candidate| {
+            Ok(if c.interface == 1 || c.path == "/dev/hidraw8" {
+                vec![4]
+            } else {
+                vec![]
+            })
+        };
+        let paths =
+            |found: Vec<&Candidate>| found.iter().map(|c| c.path.clone()).collect::<Vec<_>>();
+
+        let found = all_config_interfaces(&list, &Selector::default(), vendor).unwrap();
+        assert_eq!(
+            paths(found),
+            [
+                "/dev/hidraw3",
+                "/dev/hidraw7",
+                "/dev/hidraw8",
+                "/dev/hidraw5"
+            ]
+        );
+
+        let by_interface = Selector {
+            interface: Some(0),
+            ..Selector::default()
+        };
+        let found = all_config_interfaces(&list, &by_interface, vendor).unwrap();
+        assert_eq!(
+            paths(found),
+            [
+                "/dev/hidraw2",
+                "/dev/hidraw6",
+                "/dev/hidraw8",
+                "/dev/hidraw4"
+            ]
+        );
+    }
+
+    #[test]
+    fn units_are_named_by_port_only_when_they_collide() {
+        let on = |path: &str, serial: Option<&str>, unit: &str| Candidate {
+            unit: Some(unit.into()),
+            ..candidate(path, serial, 1)
+        };
+        let list = [
+            on("/dev/hidraw3", Some("A"), "1-2:1"),
+            on("/dev/hidraw5", Some("A"), "1-4:1"),
+            on("/dev/hidraw7", Some("B"), "1-6:1"),
+            on("/dev/hidraw9", None, "1-8:1"),
+            on("/dev/hidraw10", None, "1-8:1"),
+        ];
+        let names: Vec<_> = list.iter().map(|c| unit_name(c, &list)).collect();
+        assert_eq!(
+            names,
+            [
+                Some("A at 1-2:1".into()),
+                Some("A at 1-4:1".into()),
+                Some("B".into()),
+                None,
+                None
+            ]
+        );
+        assert_eq!(device_key(&list[2], &list), "1bcf-08a0-B");
+        assert_eq!(device_key(&list[3], &list), "1bcf-08a0");
+
+        // A second mouse without a serial makes both keyed by port.
+        let two = [list[3].clone(), on("/dev/hidraw11", None, "1-9:1")];
+        assert_eq!(device_key(&two[0], &two), "1bcf-08a0-1_8_1");
+        assert_eq!(device_key(&two[1], &two), "1bcf-08a0-1_9_1");
+    }
+}
@@ -0,0 +1,500 @@
+use crate::device::{PacketResult, bytes_to_hex};
+use crate::image;
+use crate::output::{self, detail, say};
+use crate::pacing::Pacing;
+use crate::transport::Transport;
+use crate::{RESET, YELLOW};
+use serde::Serialize;
+use std::{
+    fmt,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+/// How long to wait between GET_REPORTs while a packet isn't acknowledged.
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+/// One step of a write, named after what the official driver's packets
+/// appear to do. The opcode is byte 1 of the packet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Step {
+    Begin,
+    Mode,
+    Header,
+    Data,
+    End,
+    Commit,
+}
+
+impl Step {
+    pub fn from_opcode(opcode: u8) -> Option<Self> {
+        Some(match opcode {
+            0x01 => Step::Begin,
+            0x03 => Step::Mode,
+            0x06 => Step::Header,
+            image::OP_DATA => Step::Data,
+            0x08 => Step::End,
+            0x02 => Step::Commit,
+            _ => return None,
+        })
+    }
+
+    pub fn opcode(self) -> u8 {
+        match self {
+            Step::Begin => 0x01,
+            Step::Mode => 0x03,
+            Step::Header => 0x06,
+            Step::Data => image::OP_DATA,
+            Step::End => 0x08,
+            Step::Commit => 0x02,
+        }
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Step::Begin => "begin",
+            Step::Mode => "mode select",
+            Step::Header => "header",
+            Step::Data => "data page",
+            Step::End => "end",
+            Step::Commit => "commit",
+        };
+        write!(f, "{name} (04{:02x})", self.opcode())
+    }
+}
+
+/// Why a write session stopped. Each names the step it stopped at.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SessionError {
+    /// Packet `index` isn't a known step, or can't follow the previous one.
+    OutOfOrder {
+        index: usize,
+        opcode: u8,
+        after: Option<Step>,
+    },
+    /// The SET_REPORT for packet `index` failed.
+    Send {
+        index: usize,
+        step: Step,
+        error: String,
+    },
+    /// The device never handed packet `index` back.
+    NotAcknowledged {
+        index: usize,
+        step: Step,
+        tries: usize,
+        readback: Option<String>,
+    },
+    /// The packets ran out before the commit.
+    Incomplete { after: Option<Step> },
+}
+
+impl SessionError {
+    /// The step the session was at or trying to reach.
+    pub fn step(&self) -> Option<Step> {
+        match self {
+            SessionError::OutOfOrder { opcode, .. } => Step::from_opcode(*opcode),
+            SessionError::Send { step, .. } | SessionError::NotAcknowledged { step, .. } => {
+                Some(*step)
+            }
+            SessionError::Incomplete { after } => *after,
+        }
+    }
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let after = |after: &Option<Step>| match after {
+            Some(step) => format!("after {step}"),
+            None => "at the start".to_owned(),
+        };
+        match self {
+            SessionError::OutOfOrder {
+                index,
+                opcode,
+                after: prev,
+            } => match Step::from_opcode(*opcode) {
+                Some(step) => write!(f, "packet {index}: {step} can't come {}", after(prev)),
+                None => write!(f, "packet {index}: unknown opcode {opcode:02x}"),
+            },
+            SessionError::Send { index, step, error } => {
+                write!(f, "{step}, packet {index}: send failed: {error}")
+            }
+            SessionError::NotAcknowledged {
+                index,
+                step,
+                tries,
+                readback,
+            } => {
+                write!(
+                    f,
+                    "{step}, packet {index}: not acknowledged after {tries} tries"
+                )?;
+                match readback {
+                    Some(got) => write!(f, ", device answered {got}"),
+                    None => write!(f, ", no answer"),
+                }
+            }
+            SessionError::Incomplete { after: prev } => {
+                write!(f, "session ended {} without a commit", after(prev))
+            }
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+/// Where `step` may go next, given `pages` data pages so far out of the
+/// `image::DATA_COUNT` the header announces.
+fn follows(prev: Option<Step>, pages: usize, step: Step) -> bool {
+    matches!(
+        (prev, step),
+        (None, Step::Begin)
+            | (Some(Step::Begin), Step::Mode)
+            | (Some(Step::Mode), Step::Header)
+            | (Some(Step::Header), Step::Data)
+            | (Some(Step::End), Step::Commit)
+    ) || (prev == Some(Step::Data) && step == Step::Data && pages < image::DATA_COUNT)
+        || (prev == Some(Step::Data) && step == Step::End && pages == image::DATA_COUNT)
+}
+
+/// Checks that `packets` form one complete session, without sending anything.
+pub fn check(packets: &[Vec<u8>]) -> Result<(), SessionError> {
+    let mut prev = None;
+    let mut pages = 0;
+    for (index, packet) in packets.iter().enumerate() {
+        let opcode = packet.get(1).copied().unwrap_or_default();
+        match Step::from_opcode(opcode) {
+            Some(step) if follows(prev, pages, step) => {
+                pages += usize::from(step == Step::Data);
+                prev = Some(step);
+            }
+            _ => {
+                return Err(SessionError::OutOfOrder {
+                    index,
+                    opcode,
+                    after: prev,
+                });
+            }
+        }
+    }
+    match prev {
+        Some(Step::Commit) => Ok(()),
+        after => Err(SessionError::Incomplete { after }),
+    }
+}
+
+/// One write to the device: begin, mode select, a header announcing
+/// `image::DATA_COUNT` data pages, the pages, end and commit, in that order.
+/// Every packet has to be acknowledged (handed back on GET_REPORT) before
+/// the next one goes out. If the first packet never is, the device is taken
+/// not to acknowledge at all: the rest go out `Pacing::unacknowledged_delay`
+/// apart, each recorded as not `ok`, and it's up to the caller not to count
+/// such a write as done.
+///
+/// Only begin and mode select are resent when unacknowledged. Data pages
+/// carry no offset, so a page resent after a late acknowledgement would be
+/// stored twice and shift every page after it; from the header on, a packet
+/// that isn't acknowledged ends the session and the write starts over.
+pub struct Session<'a, T: Transport> {
+    dev: &'a T,
+    pacing: &'a Pacing,
+    results: &'a mut Vec<PacketResult>,
+    prev: Option<Step>,
+    pages: usize,
+    sent: usize,
+    unacknowledged: bool,
+}
+
+impl<'a, T: Transport> Session<'a, T> {
+    /// Appends one entry per packet to `results`, including the one that
+    /// failed.
+    pub fn new(dev: &'a T, pacing: &'a Pacing, results: &'a mut Vec<PacketResult>) -> Self {
+        Self {
+            dev,
+            pacing,
+            results,
+            prev: None,
+            pages: 0,
+            sent: 0,
+            unacknowledged: false,
+        }
+    }
+
+    /// Sends the next packet of the session and waits for it to be
+    /// acknowledged.
+    pub fn send(&mut self, packet: &[u8]) -> Result<Step, SessionError> {
+        let index = self.sent;
+        let opcode = packet.get(1).copied().unwrap_or_default();
+        let step = match Step::from_opcode(opcode) {
+            Some(step) if follows(self.prev, self.pages, step) => step,
+            _ => {
+                return Err(SessionError::OutOfOrder {
+                    index,
+                    opcode,
+                    after: self.prev,
+                });
+            }
+        };
+
+        let mut result = PacketResult {
+            attempt: 1,
+            index,
+            sent: bytes_to_hex(packet),
+            ok: false,
+            tries: 0,
+            readback: None,
+            latency_us: None,
+            error: None,
+        };
+        let retries = if matches!(step, Step::Begin | Step::Mode) {
+            self.pacing.retries
+        } else {
+            0
+        };
+        while !result.ok && result.tries <= retries {
+            if result.tries > 0 {
+                say!("{YELLOW}WARN:{RESET} {step}, packet {index}: not acknowledged, resending");
+            }
+            result.tries += 1;
+            output::packet(format_args!("> SET_REPORT {}", result.sent), None);
+            let sent_at = Instant::now();
+            if let Err(e) = self.dev.send_feature_report(packet) {
+                detail!("{step}, packet {index}: send failed: {e}");
+                result.error = Some(e.to_string());
+                self.results.push(result);
+                return Err(SessionError::Send {
+                    index,
+                    step,
+                    error: e.to_string(),
+                });
+            }
+            if self.unacknowledged {
+                sleep(self.pacing.unacknowledged_delay());
+                break;
+            }
+            self.wait_for_ack(packet, sent_at, &mut result);
+        }
+        if !result.ok && index == 0 {
+            say!(
+                "{YELLOW}WARN:{RESET} {step} wasn't acknowledged, sending the rest {} ms apart \
+                 without acknowledgements; the write can't be confirmed",
+                self.pacing.unacknowledged_delay().as_millis()
+            );
+            self.unacknowledged = true;
+        }
+        let (ok, tries, readback) = (result.ok, result.tries, result.readback.clone());
+        self.results.push(result);
+        if !ok && !self.unacknowledged {
+            return Err(SessionError::NotAcknowledged {
+                index,
+                step,
+                tries,
+                readback,
+            });
+        }
+
+        self.sent += 1;
+        self.pages += usize::from(step == Step::Data);
+        self.prev = Some(step);
+        Ok(step)
+    }
+
+    /// Ends the session, failing unless the commit went out.
+    pub fn finish(self) -> Result<(), SessionError> {
+        match self.prev {
+            Some(Step::Commit) => Ok(()),
+            after => Err(SessionError::Incomplete { after }),
+        }
+    }
+
+    /// Polls GET_REPORT until the device hands `packet` back or the timeout
+    /// runs out, recording what it saw in `result`.
+    fn wait_for_ack(&self, packet: &[u8], sent_at: Instant, result: &mut PacketResult) {
+        sleep(self.pacing.min_delay());
+        let deadline = Instant::now() + self.pacing.timeout();
+        loop {
+            let mut buf = packet.to_vec();
+            match self.dev.get_feature_report(&mut buf) {
+                Ok(len) => {
+                    let latency = sent_at.elapsed();
+                    output::packet(
+                        format_args!("< GET_REPORT {}", bytes_to_hex(&buf[..len])),
+                        Some(latency),
+                    );
+                    result.readback = Some(bytes_to_hex(&buf[..len]));
+                    if buf[..len] == *packet {
+                        result.ok = true;
+                        result.latency_us = Some(latency.as_micros() as u64);
+                        result.error = None;
+                        return;
+                    }
+                }
+                Err(e) => result.error = Some(format!("readback: {e}")),
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return;
+            }
+            sleep(POLL_INTERVAL.min(deadline - now));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MouseConfig;
+    use crate::image::ConfigImage;
+    use crate::transport::mock::{MockTransport, Response};
+
+    fn reports() -> Vec<Vec<u8>> {
+        ConfigImage::encode(&MouseConfig::default()).to_reports()
+    }
+
+    fn pacing() -> Pacing {
+        Pacing {
+            min_delay_ms: 0,
+            timeout_ms: 0,
+            retries: 0,
+        }
+    }
+
+    #[test]
+    fn full_image_is_one_complete_session() {
+        assert_eq!(check(&reports()), Ok(()));
+    }
+
+    #[test]
+    fn out_of_order_packets_are_rejected() {
+        let mut packets = reports();
+        packets.swap(0, 1);
+        assert_eq!(
+            check(&packets),
+            Err(SessionError::OutOfOrder {
+                index: 0,
+                opcode: 0x03,
+                after: None
+            })
+        );
+
+        // One data page short: the end arrives while the header's pages are
+        // still due.
+        let mut packets = reports();
+        packets.remove(10);
+        let err = check(&packets).unwrap_err();
+        assert_eq!(err.step(), Some(Step::End));
+        assert!(err.to_string().contains("end (0408)"), "{err}");
+
+        let mut packets = reports();
+        packets.pop();
+        assert_eq!(
+            check(&packets),
+            Err(SessionError::Incomplete {
+                after: Some(Step::End)
+            })
+        );
+    }
+
+    #[test]
+    fn unexpected_answer_names_the_step() {
+        let dev = MockTransport::new();
+        dev.respond(Response::Echo)
+            .respond(Response::Report(vec![0x04, 0x00, 0, 0, 0, 0, 0, 0]));
+        let mut results = Vec::new();
+        let pacing = pacing();
+        let mut session = Session::new(&dev, &pacing, &mut results);
+        let packets = reports();
+        assert_eq!(session.send(&packets[0]), Ok(Step::Begin));
+        let err = session.send(&packets[1]).unwrap_err();
+        assert_eq!(
+            err,
+            SessionError::NotAcknowledged {
+                index: 1,
+                step: Step::Mode,
+                tries: 1,
+                readback: Some("0400000000000000".into()),
+            }
+        );
+        assert!(err.to_string().starts_with("mode select (0403)"), "{err}");
+        assert_eq!(results.len(), 2);
+    }
+
+    #[test]
+    fn late_acknowledged_data_page_is_not_resent() {
+        let packets = reports();
+        let page = image::DATA_START + 5;
+        let dev = MockTransport::new();
+        for _ in 0..page {
+            dev.respond(Response::Echo);
+        }
+        // The device is still on the previous page when the timeout runs
+        // out; its acknowledgement of this one would come later.
+        dev.respond(Response::Report(packets[page - 1].clone()));
+        let mut results = Vec::new();
+        let pacing = Pacing {
+            retries: 2,
+            ..pacing()
+        };
+        let mut session = Session::new(&dev, &pacing, &mut results);
+        for packet in &packets[..page] {
+            session.send(packet).unwrap();
+        }
+        let err = session.send(&packets[page]).unwrap_err();
+        assert!(matches!(
+            err,
+            SessionError::NotAcknowledged {
+                step: Step::Data,
+                tries: 1,
+                ..
+            }
+        ));
+        assert_eq!(dev.sent(), packets[..=page]);
+    }
+
+    #[test]
+    fn device_that_never_acknowledges_is_sent_everything_unacknowledged() {
+        let dev = MockTransport::new();
+        for _ in 0..3 {
+            dev.respond(Response::Error("broken pipe"));
+        }
+        let mut results = Vec::new();
+        let pacing = Pacing {
+            retries: 2,
+            ..pacing()
+        };
+        let mut session = Session::new(&dev, &pacing, &mut results);
+        for packet in &reports() {
+            session.send(packet).unwrap();
+        }
+        assert_eq!(session.finish(), Ok(()));
+        // Three tries at the first packet, then one send each and no polling.
+        assert_eq!(dev.sent().len(), reports().len() + 2);
+        assert_eq!(dev.events().len(), dev.sent().len());
+        assert!(results.iter().all(|r| !r.ok && r.latency_us.is_none()));
+    }
+
+    #[test]
+    fn session_refuses_to_skip_steps() {
+        let dev = MockTransport::new();
+        let mut results = Vec::new();
+        let pacing = pacing();
+        let mut session = Session::new(&dev, &pacing, &mut results);
+        let packets = reports();
+        session.send(&packets[0]).unwrap();
+        assert!(matches!(
+            session.send(&packets[5]),
+            Err(SessionError::OutOfOrder { index: 1, .. })
+        ));
+        assert_eq!(dev.sent(), [packets[0].clone()]);
+        assert_eq!(
+            session.finish(),
+            Err(SessionError::Incomplete {
+                after: Some(Step::Begin)
+            })
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use crate::MouseConfig;
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Last configuration that was written to a device without errors.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct State {
+    pub device: String,
+    /// Seconds since the Unix epoch.
+    pub applied_at: u64,
+    pub config: MouseConfig,
+}
+
+/// `$XDG_STATE_HOME/redgear-a15`, falling back to `~/.local/state/redgear-a15`.
+pub fn state_dir() -> Result<PathBuf> {
+    let base = match env::var_os("XDG_STATE_HOME").filter(|v| !v.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(".local/state"))
+            .ok_or_else(|| anyhow!("neither XDG_STATE_HOME nor HOME is set"))?,
+    };
+    Ok(base.join("redgear-a15"))
+}
+
+/// Identifies a unit across runs: VID/PID, plus the serial when the device
+/// reports one.
+pub fn device_key(vid: u16, pid: u16, serial: Option<&str>) -> String {
+    let mut key = format!("{vid:04x}-{pid:04x}");
+    if let Some(serial) = serial.map(str::trim).filter(|s| !s.is_empty()) {
+        key.push('-');
+        key.extend(
+            serial
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }),
+        );
+    }
+    key
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Rough "how long ago" for a timestamp from `now()`.
+pub fn describe_age(at: u64) -> String {
+    let secs = now().saturating_sub(at);
+    match secs {
+        0..60 => format!("{secs}s ago"),
+        60..3600 => format!("{}m ago", secs / 60),
+        3600..86400 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+fn state_path(dir: &Path, device: &str) -> PathBuf {
+    dir.join(format!("{device}.json"))
+}
+
+pub fn load(dir: &Path, device: &str) -> Result<Option<State>> {
+    let path = state_path(dir, device);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+    };
+    let state =
+        serde_json::from_str(&text).with_context(|| format!("parsing {}", path.display()))?;
+    Ok(Some(state))
+}
+
+/// Writes through a temp file and rename so an interrupted run can't leave a
+/// truncated state file behind.
+pub fn save(dir: &Path, state: &State) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    let path = state_path(dir, &state.device);
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(state)?)
+        .with_context(|| format!("writing {}", tmp.display()))?;
+    fs::rename(&tmp, &path).with_context(|| format!("writing {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DpiVal, LedMode};
+
+    #[test]
+    fn device_key_sanitizes_serial() {
+        assert_eq!(device_key(0x1bcf, 0x08a0, None), "1bcf-08a0");
+        assert_eq!(device_key(0x1bcf, 0x08a0, Some("  ")), "1bcf-08a0");
+        assert_eq!(
+            device_key(0x1bcf, 0x08a0, Some("A1/b 2")),
+            "1bcf-08a0-A1_b_2"
+        );
+    }
+
+    #[test]
+    fn state_round_trips() {
+        let dir = env::temp_dir().join(format!("redgear-a15-state-{}", std::process::id()));
+        let state = State {
+            device: "1bcf-08a0".into(),
+            applied_at: 42,
+            config: MouseConfig {
+                dpi: DpiVal::DPI7,
+                led_mode: LedMode::FourSeasons,
+                ..MouseConfig::default()
+            },
+        };
+        assert_eq!(load(&dir, &state.device).unwrap(), None);
+        save(&dir, &state).unwrap();
+        assert_eq!(load(&dir, &state.device).unwrap(), Some(state));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn factory_reset_is_not_saved() {
+        let dir = env::temp_dir().join(format!("redgear-a15-reset-{}", std::process::id()));
+        let state = State {
+            device: "1bcf-08a0".into(),
+            applied_at: 42,
+            config: MouseConfig::factory(),
+        };
+        save(&dir, &state).unwrap();
+        let loaded = load(&dir, &state.device).unwrap().unwrap();
+        assert!(!loaded.config.reset);
+        assert_eq!(
+            loaded.config,
+            MouseConfig {
+                reset: false,
+                ..MouseConfig::factory()
+            }
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
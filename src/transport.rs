@@ -0,0 +1,152 @@
+use anyhow::Result;
+use hidapi::HidDevice;
+
+/// The three HID calls the tool needs. `HidDevice` is the real thing; tests
+/// use `mock::MockTransport` so nothing needs a physical A-15.
+pub trait Transport {
+    fn send_feature_report(&self, data: &[u8]) -> Result<()>;
+
+    /// `buf[0]` holds the report id on entry; returns the number of bytes read.
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize>;
+
+    /// Interrupt-in read, returns 0 when nothing arrived within `timeout_ms`.
+    fn read(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize>;
+}
+
+impl Transport for HidDevice {
+    fn send_feature_report(&self, data: &[u8]) -> Result<()> {
+        Ok(HidDevice::send_feature_report(self, data)?)
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+        Ok(HidDevice::get_feature_report(self, buf)?)
+    }
+
+    fn read(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize> {
+        Ok(HidDevice::read_timeout(self, buf, timeout_ms)?)
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    use super::Transport;
+    use anyhow::{Result, bail};
+    use std::{
+        cell::RefCell,
+        collections::{HashSet, VecDeque},
+    };
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub enum Event {
+        Set(Vec<u8>),
+        Get(Vec<u8>),
+    }
+
+    #[derive(Clone, Debug)]
+    pub enum Response {
+        Report(Vec<u8>),
+        /// Hand back the last packet that was sent.
+        Echo,
+        Error(&'static str),
+    }
+
+    /// Records every call and plays back scripted GET_REPORT responses.
+    /// Once the script runs out it echoes the last SET_REPORT, which is what
+    /// the A-15 does.
+    #[derive(Default)]
+    pub struct MockTransport {
+        events: RefCell<Vec<Event>>,
+        responses: RefCell<VecDeque<Response>>,
+        reads: RefCell<VecDeque<Vec<u8>>>,
+        failing_sends: RefCell<HashSet<usize>>,
+        send_attempts: RefCell<usize>,
+    }
+
+    impl MockTransport {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn respond(&self, response: Response) -> &Self {
+            self.responses.borrow_mut().push_back(response);
+            self
+        }
+
+        pub fn respond_with(&self, reports: impl IntoIterator<Item = Vec<u8>>) -> &Self {
+            self.responses
+                .borrow_mut()
+                .extend(reports.into_iter().map(Response::Report));
+            self
+        }
+
+        pub fn queue_read(&self, report: Vec<u8>) -> &Self {
+            self.reads.borrow_mut().push_back(report);
+            self
+        }
+
+        /// Makes the `n`th send attempt (0-based, counting failures) fail.
+        pub fn fail_send(&self, n: usize) -> &Self {
+            self.failing_sends.borrow_mut().insert(n);
+            self
+        }
+
+        pub fn events(&self) -> Vec<Event> {
+            self.events.borrow().clone()
+        }
+
+        /// Packets that were sent successfully, in order.
+        pub fn sent(&self) -> Vec<Vec<u8>> {
+            self.events
+                .borrow()
+                .iter()
+                .filter_map(|e| match e {
+                    Event::Set(p) => Some(p.clone()),
+                    Event::Get(_) => None,
+                })
+                .collect()
+        }
+
+        fn last_sent(&self) -> Vec<u8> {
+            self.sent().pop().unwrap_or_default()
+        }
+    }
+
+    impl Transport for MockTransport {
+        fn send_feature_report(&self, data: &[u8]) -> Result<()> {
+            let attempt = {
+                let mut n = self.send_attempts.borrow_mut();
+                *n += 1;
+                *n - 1
+            };
+            if self.failing_sends.borrow().contains(&attempt) {
+                bail!("mock: send {attempt} failed");
+            }
+            self.events.borrow_mut().push(Event::Set(data.to_vec()));
+            Ok(())
+        }
+
+        fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+            let response = self.responses.borrow_mut().pop_front();
+            let report = match response.unwrap_or(Response::Echo) {
+                Response::Report(report) => report,
+                Response::Echo => self.last_sent(),
+                Response::Error(e) => bail!("mock: {e}"),
+            };
+            let len = report.len().min(buf.len());
+            buf[..len].copy_from_slice(&report[..len]);
+            self.events
+                .borrow_mut()
+                .push(Event::Get(buf[..len].to_vec()));
+            Ok(len)
+        }
+
+        fn read(&self, buf: &mut [u8], _timeout_ms: i32) -> Result<usize> {
+            let Some(report) = self.reads.borrow_mut().pop_front() else {
+                return Ok(0);
+            };
+            let len = report.len().min(buf.len());
+            buf[..len].copy_from_slice(&report[..len]);
+            Ok(len)
+        }
+    }
+}